@@ -0,0 +1,32 @@
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion, Throughput};
+use std::fs;
+use thanatos::{CompressionMode, Compressor, Decompressor};
+
+fn decompress(c: &mut Criterion) {
+    let mut entries = fs::read_dir("tests/decompress_data")
+        .expect("Failed to read test data")
+        .map(|entry| entry.expect("Failed to read test data").path())
+        .collect::<Vec<_>>();
+    entries.sort();
+
+    let mut group = c.benchmark_group("decompress");
+    for path in entries {
+        let name = path.file_name().unwrap().to_string_lossy().into_owned();
+        let data = fs::read(&path).expect("Failed to read test data");
+        let compressed = Compressor::with_mode(&data, CompressionMode::Greedy).compress();
+
+        group.throughput(Throughput::Bytes(data.len() as u64));
+        group.bench_with_input(
+            BenchmarkId::new("bytewise", &name),
+            &compressed,
+            |b, src| b.iter(|| Decompressor::new(src, 0).decompress().unwrap()),
+        );
+        group.bench_with_input(BenchmarkId::new("fast", &name), &compressed, |b, src| {
+            b.iter(|| Decompressor::new(src, 0).decompress_fast().unwrap())
+        });
+    }
+    group.finish();
+}
+
+criterion_group!(benches, decompress);
+criterion_main!(benches);
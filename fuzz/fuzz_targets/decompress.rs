@@ -0,0 +1,18 @@
+//! Run with `cargo +nightly fuzz run decompress`. Inputs that crash or hang belong in
+//! `tests/decompress_corpus` once they are fixed.
+
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use thanatos::Decompressor;
+
+fuzz_target!(|data: &[u8]| {
+    // the first two bytes pick the offset, which may point past the end of the data
+    let Some((offset, src)) = data.split_first_chunk::<2>() else {
+        return;
+    };
+    let offset = u16::from_le_bytes(*offset) as usize % (src.len() + 2);
+
+    let _ = Decompressor::new(src, offset).decompress();
+    let _ = Decompressor::new(src, offset).decompress_traced();
+});
@@ -0,0 +1,68 @@
+use core::{fmt, str::FromStr};
+
+/// A 24 bit address as seen by the SNES CPU.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct SnesAddress {
+    pub bank: u8,
+    pub addr: u16,
+}
+
+impl SnesAddress {
+    pub fn new(bank: u8, addr: u16) -> Self {
+        Self { bank, addr }
+    }
+
+    /// Offset into a LoROM image, if the address maps to ROM at all.
+    /// Banks $80-$FF mirror $00-$7F, each bank maps 32KiB of ROM to $8000-$FFFF.
+    pub fn lorom_offset(self) -> Option<usize> {
+        if self.addr < 0x8000 || matches!(self.bank, 0x7e | 0x7f) {
+            return None;
+        }
+
+        Some(((self.bank & 0x7f) as usize) << 15 | (self.addr & 0x7fff) as usize)
+    }
+
+    /// The LoROM address of an offset into the image, in the banks starting at $00.
+    pub fn from_lorom_offset(offset: usize) -> Self {
+        Self {
+            bank: (offset >> 15) as u8,
+            addr: 0x8000 | (offset & 0x7fff) as u16,
+        }
+    }
+}
+
+impl fmt::Display for SnesAddress {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "${:02X}:{:04X}", self.bank, self.addr)
+    }
+}
+
+/// The error returned when a string isn't an address like `$91:DDBB`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ParseAddressError;
+
+impl fmt::Display for ParseAddressError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "expected an address like $91:DDBB")
+    }
+}
+
+impl core::error::Error for ParseAddressError {}
+
+/// Parses the form [`Display`](fmt::Display) writes, the `$` is optional.
+impl FromStr for SnesAddress {
+    type Err = ParseAddressError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let s = s.strip_prefix('$').unwrap_or(s);
+        let (bank, addr) = s.split_once(':').ok_or(ParseAddressError)?;
+        if bank.is_empty() || bank.len() > 2 || addr.is_empty() || addr.len() > 4 {
+            return Err(ParseAddressError);
+        }
+
+        Ok(Self {
+            bank: u8::from_str_radix(bank, 16).map_err(|_| ParseAddressError)?,
+            addr: u16::from_str_radix(addr, 16).map_err(|_| ParseAddressError)?,
+        })
+    }
+}
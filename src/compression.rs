@@ -1,28 +1,82 @@
+use alloc::vec::Vec;
+
+mod compress;
+pub use compress::{CompressionMode, Compressor};
 mod decompress;
-pub use decompress::{DecompressError, Decompressor};
+pub use decompress::{
+    DecompressError, DecompressOptions, DecompressResult, Decompressor, ErrorContext, LoopPolicy,
+    DECODER_VERSION,
+};
+mod trace;
+pub use trace::{TraceEvent, TracedOperation};
+mod chain;
+pub use chain::{ChainEnd, ChainTable};
+mod stats;
+pub use stats::{CompressionStats, OperationKind};
+mod asm;
+pub use asm::{assemble, disassemble, AssembleError};
+mod emulation;
+pub use emulation::{EmulatedResult, EmulationError, Wram};
+
+#[cfg(feature = "decompress-old")]
+mod decompress_old;
+#[cfg(feature = "decompress-old")]
+pub use decompress_old::{decompress as decompress_old, Halt, OldDecompressResult};
+#[cfg(feature = "decompress-old")]
+mod differential;
+#[cfg(feature = "decompress-old")]
+pub use differential::{compare, compare_all, Disagreement, Mismatch};
 
 pub trait Compressable {
     fn from_compressed(data: &[u8], offset: usize) -> Result<Self, DecompressError>
     where
         Self: Sized,
     {
-        let result = Decompressor::new(data, offset).decompress()?;
-        Self::try_from_slice(&result.data)
+        Self::from_compressed_with(data, offset, DecompressOptions::default())
+    }
+
+    fn from_compressed_with(
+        data: &[u8],
+        offset: usize,
+        options: DecompressOptions,
+    ) -> Result<Self, DecompressError>
+    where
+        Self: Sized,
+    {
+        let result = Decompressor::with_options(data, offset, options).decompress()?;
+        Self::try_from_slice(&result.data).map_err(|mut error| {
+            *error.context_mut() = ErrorContext {
+                region_start: offset,
+                read_index: offset + result.bytes_read,
+                opcode: None,
+                operation: None,
+                bytes_produced: result.data.len(),
+            };
+            error
+        })
     }
 
     fn try_from_slice(data: &[u8]) -> Result<Self, DecompressError>
     where
         Self: Sized;
+
+    /// The decompressed data [`try_from_slice`](Compressable::try_from_slice) reads the value
+    /// from.
+    fn to_bytes(&self) -> Vec<u8>;
+
+    fn compress(&self) -> Vec<u8> {
+        Compressor::new(&self.to_bytes()).compress()
+    }
 }
 
-#[derive(Debug)]
-enum NibblePos {
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum NibblePos {
     Upper,
     Lower,
 }
 
-#[derive(Debug)]
-enum Operation {
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Operation {
     /// copy n bytes from src to dst
     CopySimple(u8),
 
@@ -0,0 +1,493 @@
+//! A small text syntax for writing compressed streams by hand.
+//!
+//! Every line holds one operation, comments start with `;`. Counts are the number of bytes
+//! (or nibbles/pairs) as they appear in the stream or output, not the raw values stored in the
+//! opcode. Numbers can be decimal or hex with a `0x` prefix, data is written as hex in quotes.
+//!
+//! ```text
+//! copy 4 "00 11 22 33"                    ; CopySimple: 1-64 bytes copied as-is
+//! nibble 5 upper fixed=0x0 "12345"        ; CopyNibbleFixed: 2-17 nibbles, one hex digit each
+//! nibble 3 lower fixed=0xf initial=0x3 "456"
+//! double 3 "aa bb cc"                     ; CopyDoubled: 1-16 bytes, each written twice
+//! interleave 2 fixed=0x00 first "aa bb"   ; CopyInterleaved: 2-17 bytes, fixed value first or second
+//! backread count=8 back=0x40              ; CopyBackread: 2-65 bytes from earlier output
+//! repeat 0x10 0x00                        ; RepeatValue: 3-4098 times the same value
+//! backref count=5 back=3                  ; StartBackref: re-read 3-34 earlier bytes of the stream
+//! bytes "fe"                              ; raw bytes, for anything the other forms can't express
+//! exit
+//! ```
+//!
+//! `backread`, `repeat` and `backref` use their two byte encoding when possible,
+//! adding `long` forces the three byte one.
+
+use super::{NibblePos, Operation, TraceEvent, TracedOperation};
+use alloc::{
+    collections::{BTreeMap, BTreeSet},
+    format,
+    string::{String, ToString},
+    vec::Vec,
+};
+use core::fmt::{self, Write};
+
+#[derive(Debug)]
+pub struct AssembleError {
+    pub line: usize,
+    pub message: String,
+}
+
+impl fmt::Display for AssembleError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "line {}: {}", self.line, self.message)
+    }
+}
+
+impl core::error::Error for AssembleError {}
+
+/// the arguments of a single line
+#[derive(Debug, Default)]
+struct Arguments<'a> {
+    positional: Vec<usize>,
+    named: BTreeMap<&'a str, usize>,
+    flags: BTreeSet<&'a str>,
+    data: Option<&'a str>,
+}
+
+/// Turn the text form of a compressed stream into bytes.
+pub fn assemble(src: &str) -> Result<Vec<u8>, AssembleError> {
+    let mut out = Vec::new();
+
+    for (i, line) in src.lines().enumerate() {
+        let line = strip_comment(line).trim();
+        if line.is_empty() {
+            continue;
+        }
+
+        assemble_line(line, &mut out).map_err(|message| AssembleError {
+            line: i + 1,
+            message,
+        })?;
+    }
+
+    Ok(out)
+}
+
+/// Turn a trace into the text form of the stream it was recorded from.
+/// Operations that were re-read by a backref are only listed once, as the `backref` itself.
+pub fn disassemble(trace: &[TraceEvent]) -> String {
+    let mut out = String::new();
+
+    // end of the stream read so far, everything before it was already listed
+    let mut read_end: Option<usize> = None;
+    let mut last_skipped: Option<&TracedOperation> = None;
+
+    for event in trace {
+        let TraceEvent::Operation(operation) = event else {
+            continue;
+        };
+
+        let end = read_end.unwrap_or(operation.offset);
+        if operation.offset < end {
+            last_skipped = Some(operation);
+            continue;
+        }
+
+        // an operation that was started inside a backref continued reading after it ended
+        if operation.offset > end {
+            let missing = operation.offset - end;
+            if let Some(skipped) = last_skipped {
+                let bytes = [skipped.raw.as_slice(), &skipped.payload].concat();
+                let tail = &bytes[bytes.len().saturating_sub(missing)..];
+                let _ = writeln!(
+                    out,
+                    "bytes \"{}\" ; end of {:?}, read after a backref",
+                    hex(tail),
+                    skipped.operation
+                );
+            }
+        }
+
+        let line = operation_line(operation);
+        let _ = writeln!(out, "{}", line);
+
+        read_end = Some(match operation.operation {
+            Operation::StartBackref { .. } => operation.offset + operation.raw.len(),
+            _ => operation.offset + operation.raw.len() + operation.payload.len(),
+        });
+    }
+
+    out
+}
+
+/// the text form of an operation, falling back to raw bytes if it wouldn't assemble to the same bytes
+fn operation_line(traced: &TracedOperation) -> String {
+    let raw = [traced.raw.as_slice(), &traced.payload].concat();
+    let long = traced.raw.len() == 3 && traced.operation.encoded_len() == 2;
+    let long = if long { " long" } else { "" };
+
+    let line = match traced.operation {
+        Operation::CopySimple(_) => {
+            format!("copy {} \"{}\"", traced.payload.len(), hex(&traced.payload))
+        }
+        Operation::CopyNibbleFixed {
+            count,
+            fixed,
+            fixed_pos,
+            initial,
+        } => {
+            let nibbles = count as usize + 1;
+            let digits = traced
+                .payload
+                .iter()
+                .map(|value| format!("{:02x}", value))
+                .collect::<String>();
+            let pos = match fixed_pos {
+                NibblePos::Upper => "upper",
+                NibblePos::Lower => "lower",
+            };
+            let initial = initial
+                .map(|initial| format!(" initial={:#x}", initial))
+                .unwrap_or_default();
+
+            format!(
+                "nibble {} {} fixed={:#x}{} \"{}\"",
+                nibbles,
+                pos,
+                fixed,
+                initial,
+                &digits[..nibbles.min(digits.len())]
+            )
+        }
+        Operation::CopyDoubled(_) => {
+            format!(
+                "double {} \"{}\"",
+                traced.payload.len(),
+                hex(&traced.payload)
+            )
+        }
+        Operation::CopyInterleaved {
+            fixed_value,
+            fixed_first,
+            ..
+        } => format!(
+            "interleave {} fixed={:#04x} {} \"{}\"",
+            traced.payload.len(),
+            fixed_value,
+            if fixed_first { "first" } else { "second" },
+            hex(&traced.payload)
+        ),
+        Operation::CopyBackread { count, back } => {
+            format!(
+                "backread count={} back={:#x}{}",
+                count as usize + 1,
+                back,
+                long
+            )
+        }
+        Operation::RepeatValue { count, value } => {
+            format!("repeat {} {:#04x}{}", count, value, long)
+        }
+        Operation::StartBackref { count, back } => {
+            format!("backref count={} back={}{}", count, back, long)
+        }
+        Operation::Exit => "exit".to_string(),
+    };
+
+    let mut assembled = Vec::new();
+    match assemble_line(&line, &mut assembled) {
+        Ok(()) if assembled == raw => line,
+        _ => format!("bytes \"{}\" ; {:?}", hex(&raw), traced.operation),
+    }
+}
+
+fn assemble_line(line: &str, out: &mut Vec<u8>) -> Result<(), String> {
+    let (mnemonic, rest) = line.split_once(char::is_whitespace).unwrap_or((line, ""));
+    let mut args = parse_arguments(rest)?;
+
+    let long = args.flags.remove("long");
+
+    let (operation, payload) = match mnemonic {
+        "copy" => {
+            let data = args.take_data()?;
+            let count = args.take_positional("count")?;
+            check_range("count", count, 1, 64)?;
+            check_len(count, data.len())?;
+
+            (Operation::CopySimple((count - 1) as u8), data)
+        }
+
+        "nibble" => {
+            let nibbles = args.take_nibbles()?;
+            let count = args.take_positional("count")?;
+            check_range("count", count, 2, 17)?;
+            check_len(count, nibbles.len())?;
+
+            let fixed_pos = match (args.flags.remove("upper"), args.flags.remove("lower")) {
+                (true, false) => NibblePos::Upper,
+                (false, true) => NibblePos::Lower,
+                _ => return Err("expected either 'upper' or 'lower'".to_string()),
+            };
+
+            let fixed = args.take_named("fixed")?;
+            check_range("fixed", fixed, 0, 0x0f)?;
+
+            let initial = args.named.remove("initial");
+            if let Some(initial) = initial {
+                check_range("initial", initial, 0, 0x0f)?;
+                if fixed != 0x00 && fixed != 0x0f {
+                    return Err("fixed has to be 0x0 or 0xf when using initial".to_string());
+                }
+            }
+
+            let payload = nibbles
+                .chunks(2)
+                .map(|pair| (pair[0] << 4) | pair.get(1).copied().unwrap_or(0))
+                .collect();
+
+            let operation = Operation::CopyNibbleFixed {
+                count: (count - 1) as u8,
+                fixed: fixed as u8,
+                fixed_pos,
+                initial: initial.map(|initial| initial as u8),
+            };
+            (operation, payload)
+        }
+
+        "double" => {
+            let data = args.take_data()?;
+            let count = args.take_positional("count")?;
+            check_range("count", count, 1, 16)?;
+            check_len(count, data.len())?;
+
+            (Operation::CopyDoubled((count - 1) as u8), data)
+        }
+
+        "interleave" => {
+            let data = args.take_data()?;
+            let count = args.take_positional("count")?;
+            check_range("count", count, 2, 17)?;
+            check_len(count, data.len())?;
+
+            let fixed_value = args.take_named("fixed")?;
+            check_range("fixed", fixed_value, 0, 0xff)?;
+
+            let fixed_first = match (args.flags.remove("first"), args.flags.remove("second")) {
+                (true, false) => true,
+                (false, true) => false,
+                _ => return Err("expected either 'first' or 'second'".to_string()),
+            };
+
+            let operation = Operation::CopyInterleaved {
+                count: (count - 1) as u8,
+                fixed_value: fixed_value as u8,
+                fixed_first,
+            };
+            (operation, data)
+        }
+
+        "backread" => {
+            let count = args.take_named("count")?;
+            let back = args.take_named("back")?;
+            check_range("count", count, 2, 65)?;
+            check_range("back", back, 1, 0x7fff)?;
+
+            let operation = Operation::CopyBackread {
+                count: (count - 1) as u8,
+                back: back as u16,
+            };
+            (operation, Vec::new())
+        }
+
+        "repeat" => {
+            let count = args.take_positional("count")?;
+            let value = args.take_positional("value")?;
+            check_range("count", count, 3, 0x1002)?;
+            check_range("value", value, 0, 0xff)?;
+
+            let operation = Operation::RepeatValue {
+                count: count as u16,
+                value: value as u8,
+            };
+            (operation, Vec::new())
+        }
+
+        "backref" => {
+            let count = args.take_named("count")?;
+            let back = args.take_named("back")?;
+            check_range("count", count, 3, 34)?;
+            if long || count > 10 {
+                check_range("back", back, 3, 0x2002)?;
+            } else {
+                check_range("back", back, 2, 0x2002)?;
+            }
+
+            let operation = Operation::StartBackref {
+                count: count as u16,
+                back: back as u16,
+            };
+            (operation, Vec::new())
+        }
+
+        "exit" => (Operation::Exit, Vec::new()),
+
+        "bytes" => {
+            out.extend(args.take_data()?);
+            return args.finish();
+        }
+
+        _ => return Err(format!("unknown operation '{}'", mnemonic)),
+    };
+
+    args.finish()?;
+
+    if long {
+        operation.encode_long(out);
+    } else {
+        operation.encode(out);
+    }
+    out.extend(payload);
+
+    Ok(())
+}
+
+fn parse_arguments(src: &str) -> Result<Arguments<'_>, String> {
+    let mut args = Arguments::default();
+
+    let mut rest = src.trim_start();
+    while !rest.is_empty() {
+        if let Some(quoted) = rest.strip_prefix('"') {
+            let (data, after) = quoted
+                .split_once('"')
+                .ok_or_else(|| "unterminated data string".to_string())?;
+            if args.data.replace(data).is_some() {
+                return Err("only one data string is allowed".to_string());
+            }
+            rest = after.trim_start();
+            continue;
+        }
+
+        let (token, after) = rest.split_once(char::is_whitespace).unwrap_or((rest, ""));
+        rest = after.trim_start();
+
+        if let Some((name, value)) = token.split_once('=') {
+            args.named.insert(name, parse_number(value)?);
+        } else if token.starts_with(|c: char| c.is_ascii_digit()) {
+            args.positional.push(parse_number(token)?);
+        } else {
+            args.flags.insert(token);
+        }
+    }
+
+    // positional arguments are popped off the end
+    args.positional.reverse();
+    Ok(args)
+}
+
+impl Arguments<'_> {
+    fn take_positional(&mut self, name: &str) -> Result<usize, String> {
+        self.positional
+            .pop()
+            .ok_or_else(|| format!("missing {}", name))
+    }
+
+    fn take_named(&mut self, name: &str) -> Result<usize, String> {
+        self.named
+            .remove(name)
+            .ok_or_else(|| format!("missing {}=", name))
+    }
+
+    fn take_data(&mut self) -> Result<Vec<u8>, String> {
+        let digits = self.take_nibbles()?;
+        if digits.len() % 2 != 0 {
+            return Err("data has an odd number of hex digits".to_string());
+        }
+
+        Ok(digits
+            .chunks_exact(2)
+            .map(|pair| (pair[0] << 4) | pair[1])
+            .collect())
+    }
+
+    fn take_nibbles(&mut self) -> Result<Vec<u8>, String> {
+        let data = self
+            .data
+            .take()
+            .ok_or_else(|| "missing data string".to_string())?;
+
+        data.chars()
+            .filter(|c| !c.is_whitespace())
+            .map(|c| {
+                c.to_digit(16)
+                    .map(|digit| digit as u8)
+                    .ok_or_else(|| format!("invalid hex digit '{}' in data", c))
+            })
+            .collect()
+    }
+
+    /// make sure every argument was used
+    fn finish(&self) -> Result<(), String> {
+        if let Some(name) = self.named.keys().next() {
+            return Err(format!("unexpected argument '{}='", name));
+        }
+        if let Some(flag) = self.flags.iter().next() {
+            return Err(format!("unexpected argument '{}'", flag));
+        }
+        if !self.positional.is_empty() {
+            return Err("too many arguments".to_string());
+        }
+        if self.data.is_some() {
+            return Err("unexpected data string".to_string());
+        }
+
+        Ok(())
+    }
+}
+
+fn parse_number(value: &str) -> Result<usize, String> {
+    let result = match value.strip_prefix("0x") {
+        Some(hex) => usize::from_str_radix(hex, 16),
+        None => value.parse(),
+    };
+
+    result.map_err(|_| format!("invalid number '{}'", value))
+}
+
+fn check_range(name: &str, value: usize, min: usize, max: usize) -> Result<(), String> {
+    if (min..=max).contains(&value) {
+        Ok(())
+    } else {
+        Err(format!(
+            "{} has to be between {} and {}, was {}",
+            name, min, max, value
+        ))
+    }
+}
+
+fn check_len(count: usize, len: usize) -> Result<(), String> {
+    if count == len {
+        Ok(())
+    } else {
+        Err(format!(
+            "count is {} but the data has {} entries",
+            count, len
+        ))
+    }
+}
+
+fn strip_comment(line: &str) -> &str {
+    let mut in_string = false;
+    for (i, c) in line.char_indices() {
+        match c {
+            '"' => in_string = !in_string,
+            ';' if !in_string => return &line[..i],
+            _ => {}
+        }
+    }
+    line
+}
+
+fn hex(data: &[u8]) -> String {
+    data.iter()
+        .map(|value| format!("{:02x}", value))
+        .collect::<Vec<_>>()
+        .join(" ")
+}
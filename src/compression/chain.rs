@@ -0,0 +1,129 @@
+//! Cheap pre-validation of every offset in a block of data, for scanning it for compressed
+//! streams.
+//!
+//! Where the operation following an operation starts and how much it writes only depends on the
+//! bytes of the operation itself, up to the first `StartBackref`. Every offset inside a stream
+//! shares the rest of the chain with the stream, so following the chains backwards from the end
+//! of the data visits every offset only once.
+
+use super::{DecompressOptions, Decompressor, Operation};
+use alloc::{vec, vec::Vec};
+
+/// Where following the operations starting at an offset leads.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ChainEnd {
+    /// an `Exit` at the given offset
+    Exit(usize),
+    /// a `StartBackref` at `at`, moving the read index to `to`. The chain isn't followed any
+    /// further, since the operations after it depend on what the backref re-reads.
+    Backref { at: usize, to: usize },
+    /// an operation runs past the end of the data, or a backref before its start
+    Invalid,
+}
+
+#[derive(Debug, Clone, Copy)]
+struct Link {
+    end: ChainEnd,
+    /// bytes written from this offset to the end of the chain
+    output: usize,
+    /// `output` a chain has to start with for every `CopyBackread` on it to stay within the
+    /// data written so far
+    required: usize,
+}
+
+/// The [`ChainEnd`] of every offset of the data, along with what the operations up to it need.
+#[derive(Debug, Clone)]
+pub struct ChainTable {
+    links: Vec<Link>,
+}
+
+impl ChainTable {
+    pub fn new(src: &[u8]) -> Self {
+        let invalid = Link {
+            end: ChainEnd::Invalid,
+            output: 0,
+            required: 0,
+        };
+        let mut links = vec![invalid; src.len()];
+
+        for offset in (0..src.len()).rev() {
+            links[offset] = match next_operation(src, offset) {
+                Some((Operation::Exit, _)) => Link {
+                    end: ChainEnd::Exit(offset),
+                    ..invalid
+                },
+                Some((Operation::StartBackref { back, .. }, next)) => Link {
+                    end: next
+                        .checked_sub(back as usize)
+                        .map_or(ChainEnd::Invalid, |to| ChainEnd::Backref { at: offset, to }),
+                    ..invalid
+                },
+                Some((operation, next)) => {
+                    let next = links.get(next).copied().unwrap_or(invalid);
+                    let output = next.output.saturating_add(operation.output_len());
+
+                    let required = match operation {
+                        // reading the byte that's just being written never works
+                        Operation::CopyBackread { back: 0, .. } => usize::MAX,
+                        Operation::CopyBackread { back, .. } => {
+                            output.saturating_add(back as usize)
+                        }
+                        _ => 0,
+                    };
+
+                    Link {
+                        end: next.end,
+                        output,
+                        required: required.max(next.required),
+                    }
+                }
+                None => invalid,
+            };
+        }
+
+        Self { links }
+    }
+
+    pub fn end(&self, offset: usize) -> ChainEnd {
+        self.links
+            .get(offset)
+            .map_or(ChainEnd::Invalid, |link| link.end)
+    }
+
+    /// Whether decompressing at `offset` without any history can succeed at all with `options`.
+    /// If this is `false` the [`Decompressor`] is certain to fail, if it is `true` it still might.
+    pub fn may_decompress(&self, offset: usize, options: &DecompressOptions) -> bool {
+        let Some(link) = self.links.get(offset) else {
+            return false;
+        };
+
+        let within_span = |end: usize| {
+            options
+                .max_input_span
+                .is_none_or(|span| end - offset < span)
+        };
+        let reachable = match link.end {
+            ChainEnd::Exit(at) => within_span(at),
+            ChainEnd::Backref { at, to } => {
+                within_span(at) && (to >= offset || options.allow_backref_before_start)
+            }
+            ChainEnd::Invalid => false,
+        };
+
+        reachable && link.output >= link.required && link.output <= options.max_output
+    }
+}
+
+/// The operation at `offset` and the offset of the one following it,
+/// `None` if the operation doesn't fit into the data.
+fn next_operation(src: &[u8], offset: usize) -> Option<(Operation, usize)> {
+    let opcode = *src.get(offset)?;
+
+    let mut decompressor =
+        Decompressor::with_options(src, offset + 1, DecompressOptions::permissive());
+    let operation = Operation::decode(opcode, &mut decompressor).ok()?;
+    let next = offset + 1 + decompressor.bytes_read() + operation.payload_len();
+
+    // the payload of the last operation before an exit has to be there as well
+    (next <= src.len()).then_some((operation, next))
+}
@@ -0,0 +1,658 @@
+use super::{NibblePos, Operation};
+use alloc::{vec, vec::Vec};
+
+/// how far back a `CopyBackread` can reach into the output
+const BACKREAD_WINDOW: usize = 0x7fff;
+const BACKREAD_WINDOW_SMALL: usize = 0x3ff;
+const BACKREAD_MAX: usize = 65;
+const BACKREAD_MAX_SMALL: usize = 17;
+
+const LITERAL_MAX: usize = 64;
+const DOUBLED_MAX: usize = 16;
+const INTERLEAVED_MAX: usize = 17;
+const NIBBLES_MAX: usize = 17;
+
+const REPEAT_MIN: usize = 3;
+const REPEAT_MAX_SMALL: usize = 10;
+const REPEAT_MAX: usize = 0x1002;
+
+/// how far back a `StartBackref` can move the read index
+const BACKREF_WINDOW: usize = 0x2002;
+const BACKREF_WINDOW_SMALL: usize = 65;
+const BACKREF_MIN: usize = 3;
+const BACKREF_MAX: usize = 34;
+const BACKREF_MAX_SMALL: usize = 10;
+
+/// how many earlier positions are checked when looking for a `CopyBackread` match
+const MATCH_CHAIN_LIMIT: usize = 128;
+
+/// Compresses data into the format read by [`Decompressor`](super::Decompressor).
+#[derive(Debug, Clone)]
+pub struct Compressor<'a> {
+    src: &'a [u8],
+    mode: CompressionMode,
+}
+
+/// How the compressor decides which operations to use.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum CompressionMode {
+    /// Find the sequence of operations resulting in the smallest output.
+    #[default]
+    Optimal,
+
+    /// Encode from front to back, always taking the operation producing the most bytes out of
+    /// the ones that are smaller than the data they produce, the first one in opcode order on
+    /// ties. Everything else is collected into literal copies. This is how an encoder of the
+    /// time would work and is meant to reproduce the streams in the game, which the
+    /// `verify-compression` command checks.
+    Greedy,
+}
+
+/// an operation planned by the compressor, producing `len` bytes of the input starting at `start`
+#[derive(Debug, Clone)]
+struct Token {
+    start: usize,
+    len: usize,
+    operation: Operation,
+}
+
+#[derive(Debug, Clone, Copy, Default)]
+struct Match {
+    len: usize,
+    back: usize,
+}
+
+/// best `CopyBackread` matches for a position, for the small and large encoding
+#[derive(Debug, Clone, Copy, Default)]
+struct Matches {
+    small: Match,
+    large: Match,
+}
+
+impl Operation {
+    /// Number of bytes the operation and its arguments take up in a compressed stream.
+    /// This doesn't include the data copied from the stream by some operations.
+    pub fn encoded_len(&self) -> usize {
+        match self {
+            Operation::CopySimple(_) | Operation::CopyDoubled(_) | Operation::Exit => 1,
+            Operation::CopyNibbleFixed { .. } | Operation::CopyInterleaved { .. } => 2,
+            Operation::CopyBackread { count, back } => {
+                if *count <= 16 && *back <= 0x3ff {
+                    2
+                } else {
+                    3
+                }
+            }
+            Operation::RepeatValue { count, .. } => {
+                if *count <= 10 {
+                    2
+                } else {
+                    3
+                }
+            }
+            Operation::StartBackref { count, back } => {
+                if *count <= 10 && (2..=65).contains(back) {
+                    2
+                } else {
+                    3
+                }
+            }
+        }
+    }
+
+    /// Number of bytes the operation copies from the stream, following its arguments.
+    pub fn payload_len(&self) -> usize {
+        match self {
+            Operation::CopySimple(count) | Operation::CopyDoubled(count) => *count as usize + 1,
+            // an initial nibble is part of the arguments, so it doesn't change the length
+            Operation::CopyNibbleFixed { count, .. } => (*count as usize + 2) / 2,
+            Operation::CopyInterleaved { count, .. } => *count as usize + 1,
+            _ => 0,
+        }
+    }
+
+    /// Number of bytes the operation writes to the output.
+    pub fn output_len(&self) -> usize {
+        match self {
+            Operation::CopySimple(count) => *count as usize + 1,
+            Operation::CopyNibbleFixed { count, initial, .. } => {
+                *count as usize + 1 + usize::from(initial.is_some())
+            }
+            Operation::CopyDoubled(count) => 2 * (*count as usize + 1),
+            Operation::CopyInterleaved { count, .. } => 2 * (*count as usize + 1),
+            Operation::CopyBackread { count, .. } => *count as usize + 1,
+            Operation::RepeatValue { count, .. } => *count as usize,
+            Operation::StartBackref { .. } | Operation::Exit => 0,
+        }
+    }
+
+    /// Append the operation and its arguments to `out`, using the shortest encoding available.
+    /// Data copied from the stream by the operation has to be appended by the caller.
+    pub fn encode(&self, out: &mut Vec<u8>) {
+        self.encode_form(self.encoded_len() < 3, out);
+    }
+
+    /// Like [`Operation::encode`], but always uses the three byte encoding for operations that
+    /// have one, even if the operation would fit into two bytes.
+    pub fn encode_long(&self, out: &mut Vec<u8>) {
+        self.encode_form(false, out);
+    }
+
+    fn encode_form(&self, short: bool, out: &mut Vec<u8>) {
+        match *self {
+            Operation::CopySimple(count) => out.push(count),
+
+            Operation::CopyNibbleFixed {
+                count,
+                fixed,
+                ref fixed_pos,
+                initial,
+            } => {
+                out.push(0x40 | (count - 1));
+
+                let pos_bit = match fixed_pos {
+                    NibblePos::Upper => 0x00,
+                    NibblePos::Lower => 0x10,
+                };
+                out.push(match initial {
+                    None => pos_bit | fixed,
+                    Some(initial) => {
+                        let fixed_bit = if fixed == 0 { 0x00 } else { 0x40 };
+                        0x80 | fixed_bit | pos_bit | initial
+                    }
+                });
+            }
+
+            Operation::CopyDoubled(count) => out.push(0x50 | count),
+
+            Operation::CopyInterleaved {
+                count,
+                fixed_value,
+                fixed_first,
+            } => {
+                let op = if fixed_first { 0x60 } else { 0x70 };
+                out.push(op | (count - 1));
+                out.push(fixed_value);
+            }
+
+            Operation::CopyBackread { count, back } => {
+                let [back_upper, back_lower] = back.to_be_bytes();
+                if short {
+                    out.push(0x80 | ((count - 1) << 2) | back_upper);
+                } else {
+                    let count = count - 1;
+                    out.push(0xc0 | (count >> 1));
+                    out.push(((count & 0x01) << 7) | back_upper);
+                }
+                out.push(back_lower);
+            }
+
+            Operation::RepeatValue { count, value } => {
+                let count = count - 3;
+                if short {
+                    out.push(0xf0 | count as u8);
+                } else {
+                    let [upper, lower] = count.to_be_bytes();
+                    out.push(0xe0 | upper);
+                    out.push(lower);
+                }
+                out.push(value);
+            }
+
+            Operation::StartBackref { count, back } => {
+                let count = count - 3;
+                if short {
+                    out.push(0xfc | (count >> 2) as u8);
+                    out.push(((count as u8 & 0x03) << 6) | (back - 2) as u8);
+                } else {
+                    let [back_upper, back_lower] = (back - 3).to_be_bytes();
+                    out.push(0xf8 | (count >> 3) as u8);
+                    out.push(((count as u8 & 0x07) << 5) | back_upper);
+                    out.push(back_lower);
+                }
+            }
+
+            Operation::Exit => out.push(0xff),
+        }
+    }
+}
+
+impl<'a> Compressor<'a> {
+    pub fn new(src: &'a [u8]) -> Self {
+        Self::with_mode(src, CompressionMode::default())
+    }
+
+    pub fn with_mode(src: &'a [u8], mode: CompressionMode) -> Self {
+        Self { src, mode }
+    }
+
+    pub fn compress(self) -> Vec<u8> {
+        let tokens = match self.mode {
+            CompressionMode::Optimal => self.plan(),
+            CompressionMode::Greedy => self.plan_greedy(),
+        };
+        let encoded = tokens
+            .iter()
+            .map(|token| self.encode_token(token))
+            .collect::<Vec<_>>();
+
+        let mut dst = Vec::new();
+        self.emit(&encoded, &mut dst);
+        Operation::Exit.encode(&mut dst);
+
+        dst
+    }
+
+    /// find the cheapest sequence of operations producing `src`
+    fn plan(&self) -> Vec<Token> {
+        let len = self.src.len();
+        let matches = self.find_matches();
+        let runs = self.find_runs();
+
+        let mut cost = vec![usize::MAX; len + 1];
+        let mut choice: Vec<Option<(usize, Operation)>> = vec![None; len + 1];
+        cost[len] = 0;
+
+        let mut candidates = Vec::new();
+        for i in (0..len).rev() {
+            candidates.clear();
+            self.candidates(i, runs[i], matches[i], &mut candidates);
+
+            for (op_len, op_cost, operation) in candidates.drain(..) {
+                let total = op_cost + cost[i + op_len];
+                let better = match &choice[i] {
+                    None => true,
+                    Some((best_len, _)) => {
+                        total < cost[i] || (total == cost[i] && op_len > *best_len)
+                    }
+                };
+
+                if better {
+                    cost[i] = total;
+                    choice[i] = Some((op_len, operation));
+                }
+            }
+        }
+
+        let mut tokens = Vec::new();
+        let mut i = 0;
+        while i < len {
+            let (op_len, operation) = choice[i].take().expect("every position has a literal");
+            tokens.push(Token {
+                start: i,
+                len: op_len,
+                operation,
+            });
+            i += op_len;
+        }
+
+        tokens
+    }
+
+    /// take the longest operation at each position, see [`CompressionMode::Greedy`]
+    fn plan_greedy(&self) -> Vec<Token> {
+        let len = self.src.len();
+        let matches = self.find_matches();
+        let runs = self.find_runs();
+
+        let mut tokens = Vec::new();
+        let mut literal_start = 0;
+        let flush_literal = |tokens: &mut Vec<Token>, start: usize, end: usize| {
+            if end > start {
+                tokens.push(Token {
+                    start,
+                    len: end - start,
+                    operation: Operation::CopySimple((end - start - 1) as u8),
+                });
+            }
+        };
+
+        let mut candidates = Vec::new();
+        let mut i = 0;
+        while i < len {
+            candidates.clear();
+            self.candidates(i, runs[i], matches[i], &mut candidates);
+
+            let best = candidates
+                .drain(..)
+                .filter(|(op_len, op_cost, operation)| {
+                    !matches!(operation, Operation::CopySimple(_)) && op_cost < op_len
+                })
+                .fold(None, |best, candidate| match best {
+                    Some(best @ (best_len, _, _)) if best_len >= candidate.0 => Some(best),
+                    _ => Some(candidate),
+                });
+
+            match best {
+                Some((op_len, _, operation)) => {
+                    flush_literal(&mut tokens, literal_start, i);
+                    tokens.push(Token {
+                        start: i,
+                        len: op_len,
+                        operation,
+                    });
+                    i += op_len;
+                    literal_start = i;
+                }
+                None => {
+                    i += 1;
+                    if i - literal_start == LITERAL_MAX {
+                        flush_literal(&mut tokens, literal_start, i);
+                        literal_start = i;
+                    }
+                }
+            }
+        }
+        flush_literal(&mut tokens, literal_start, len);
+
+        tokens
+    }
+
+    /// all operations that could produce the data at `i` in opcode order, as `(length, cost, operation)`
+    fn candidates(
+        &self,
+        i: usize,
+        run: usize,
+        matches: Matches,
+        out: &mut Vec<(usize, usize, Operation)>,
+    ) {
+        let src = self.src;
+        let remaining = src.len() - i;
+
+        for len in 1..=remaining.min(LITERAL_MAX) {
+            out.push((len, 1 + len, Operation::CopySimple((len - 1) as u8)));
+        }
+
+        for fixed_pos in [NibblePos::Upper, NibblePos::Lower] {
+            let split = |value: u8| match fixed_pos {
+                NibblePos::Upper => (value >> 4, value & 0x0f),
+                NibblePos::Lower => (value & 0x0f, value >> 4),
+            };
+            let (fixed, first) = split(src[i]);
+
+            let nibbles = src[i..]
+                .iter()
+                .take_while(|&&value| split(value).0 == fixed)
+                .take(NIBBLES_MAX + 1)
+                .count();
+
+            for count in 2..=nibbles.min(NIBBLES_MAX) {
+                let operation = Operation::CopyNibbleFixed {
+                    count: (count - 1) as u8,
+                    fixed,
+                    fixed_pos,
+                    initial: None,
+                };
+                out.push((count, 2 + count.div_ceil(2), operation));
+            }
+
+            // the initial nibble is stored in the operation, but only works for these fixed values
+            if fixed == 0x00 || fixed == 0x0f {
+                for count in 3..=nibbles {
+                    let operation = Operation::CopyNibbleFixed {
+                        count: (count - 2) as u8,
+                        fixed,
+                        fixed_pos,
+                        initial: Some(first),
+                    };
+                    out.push((count, 2 + (count - 1).div_ceil(2), operation));
+                }
+            }
+        }
+
+        let doubled = (0..remaining / 2)
+            .take_while(|pair| src[i + pair * 2] == src[i + pair * 2 + 1])
+            .take(DOUBLED_MAX)
+            .count();
+        for pairs in 1..=doubled {
+            out.push((
+                pairs * 2,
+                1 + pairs,
+                Operation::CopyDoubled((pairs - 1) as u8),
+            ));
+        }
+
+        if remaining >= 2 {
+            for fixed_first in [true, false] {
+                let fixed_index = if fixed_first { 0 } else { 1 };
+                let fixed_value = src[i + fixed_index];
+
+                let pairs = (0..remaining / 2)
+                    .take_while(|pair| src[i + pair * 2 + fixed_index] == fixed_value)
+                    .take(INTERLEAVED_MAX)
+                    .count();
+                for pairs in 2..=pairs {
+                    let operation = Operation::CopyInterleaved {
+                        count: (pairs - 1) as u8,
+                        fixed_value,
+                        fixed_first,
+                    };
+                    out.push((pairs * 2, 2 + pairs, operation));
+                }
+            }
+        }
+
+        let backread = |len: usize, back: usize| {
+            let operation = Operation::CopyBackread {
+                count: (len - 1) as u8,
+                back: back as u16,
+            };
+            (len, operation.encoded_len(), operation)
+        };
+        out.extend((2..=matches.small.len).map(|len| backread(len, matches.small.back)));
+        if matches.large.len > matches.small.len.max(1) {
+            let shortest = matches.small.len.max(1) + 1;
+            out.extend((shortest..=matches.large.len).map(|len| backread(len, matches.large.back)));
+        }
+
+        if run >= REPEAT_MIN {
+            let repeat = |len: usize| {
+                let operation = Operation::RepeatValue {
+                    count: len as u16,
+                    value: src[i],
+                };
+                (len, operation.encoded_len(), operation)
+            };
+
+            out.extend((REPEAT_MIN..=run.min(REPEAT_MAX_SMALL)).map(repeat));
+            if run > REPEAT_MAX_SMALL {
+                out.push(repeat(run.min(REPEAT_MAX)));
+            }
+        }
+    }
+
+    /// length of the run of identical bytes starting at every position
+    fn find_runs(&self) -> Vec<usize> {
+        let mut runs = vec![1; self.src.len()];
+        for i in (0..self.src.len().saturating_sub(1)).rev() {
+            if self.src[i] == self.src[i + 1] {
+                runs[i] = runs[i + 1] + 1;
+            }
+        }
+        runs
+    }
+
+    /// find the longest earlier occurrence of the data at every position using hash chains
+    fn find_matches(&self) -> Vec<Matches> {
+        let src = self.src;
+        let mut matches = vec![Matches::default(); src.len()];
+
+        let mut head = vec![usize::MAX; 1 << 16];
+        let mut prev = vec![usize::MAX; src.len()];
+
+        for i in 0..src.len().saturating_sub(1) {
+            let key = u16::from_be_bytes([src[i], src[i + 1]]) as usize;
+
+            let mut candidate = head[key];
+            for _ in 0..MATCH_CHAIN_LIMIT {
+                if candidate == usize::MAX || i - candidate > BACKREAD_WINDOW {
+                    break;
+                }
+
+                let back = i - candidate;
+                let len = src[candidate..]
+                    .iter()
+                    .zip(&src[i..])
+                    .take(BACKREAD_MAX)
+                    .take_while(|(a, b)| a == b)
+                    .count();
+
+                let found = &mut matches[i];
+                if back <= BACKREAD_WINDOW_SMALL && len.min(BACKREAD_MAX_SMALL) > found.small.len {
+                    found.small = Match {
+                        len: len.min(BACKREAD_MAX_SMALL),
+                        back,
+                    };
+                }
+                if len > found.large.len {
+                    found.large = Match { len, back };
+                }
+
+                // the closest candidates are checked first, so there is nothing better to find
+                if found.large.len == BACKREAD_MAX {
+                    break;
+                }
+
+                candidate = prev[candidate];
+            }
+
+            prev[i] = head[key];
+            head[key] = i;
+        }
+
+        matches
+    }
+
+    fn encode_token(&self, token: &Token) -> Vec<u8> {
+        let data = &self.src[token.start..token.start + token.len];
+
+        let mut out = Vec::new();
+        token.operation.encode(&mut out);
+
+        match token.operation {
+            Operation::CopySimple(_) => out.extend_from_slice(data),
+            Operation::CopyDoubled(_) => out.extend(data.iter().step_by(2)),
+            Operation::CopyInterleaved { fixed_first, .. } => {
+                let skip = if fixed_first { 1 } else { 0 };
+                out.extend(data.iter().skip(skip).step_by(2));
+            }
+            Operation::CopyNibbleFixed {
+                ref fixed_pos,
+                initial,
+                ..
+            } => {
+                let skip = if initial.is_some() { 1 } else { 0 };
+                let nibbles = data[skip..]
+                    .iter()
+                    .map(|value| match fixed_pos {
+                        NibblePos::Upper => value & 0x0f,
+                        NibblePos::Lower => value >> 4,
+                    })
+                    .collect::<Vec<_>>();
+
+                out.extend(
+                    nibbles
+                        .chunks(2)
+                        .map(|pair| (pair[0] << 4) | pair.get(1).copied().unwrap_or(0)),
+                );
+            }
+            _ => {}
+        }
+
+        out
+    }
+
+    /// write the encoded operations to `dst`, replacing repeated sequences with a `StartBackref`
+    fn emit(&self, encoded: &[Vec<u8>], dst: &mut Vec<u8>) {
+        // positions in `dst` by their value, to quickly find backref candidates
+        let mut positions = vec![Vec::new(); 256];
+        fn push(dst: &mut Vec<u8>, positions: &mut [Vec<usize>], bytes: &[u8]) {
+            for &value in bytes {
+                positions[value as usize].push(dst.len());
+                dst.push(value);
+            }
+        }
+
+        let mut i = 0;
+        while i < encoded.len() {
+            if let Some((backref, taken)) = Self::find_backref(&encoded[i..], dst, &positions) {
+                let mut bytes = Vec::new();
+                backref.encode(&mut bytes);
+                push(dst, &mut positions, &bytes);
+
+                i += taken;
+                continue;
+            }
+
+            push(dst, &mut positions, &encoded[i]);
+            i += 1;
+        }
+    }
+
+    /// find an earlier part of the stream that is identical to the next few operations
+    fn find_backref(
+        encoded: &[Vec<u8>],
+        dst: &[u8],
+        positions: &[Vec<usize>],
+    ) -> Option<(Operation, usize)> {
+        // the next operations as one sequence, and the lengths at which an operation ends
+        let mut pattern = Vec::new();
+        let mut boundaries = Vec::new();
+        for operation in encoded {
+            if pattern.len() + operation.len() > BACKREF_MAX {
+                break;
+            }
+            pattern.extend_from_slice(operation);
+            boundaries.push(pattern.len());
+        }
+
+        if pattern.len() < BACKREF_MIN {
+            return None;
+        }
+
+        let pos = dst.len();
+        let lowest = (pos + 3).saturating_sub(BACKREF_WINDOW);
+
+        let mut best: Option<(usize, Operation, usize)> = None;
+        for &start in positions[pattern[0] as usize].iter().rev() {
+            if start < lowest {
+                break;
+            }
+
+            let matching = dst[start..]
+                .iter()
+                .zip(&pattern)
+                .take_while(|(a, b)| a == b)
+                .count();
+
+            let Some((taken, &count)) = boundaries
+                .iter()
+                .enumerate()
+                .rev()
+                .find(|(_, &count)| count <= matching && count >= BACKREF_MIN)
+            else {
+                continue;
+            };
+
+            let operation = if count <= BACKREF_MAX_SMALL && pos + 2 - start <= BACKREF_WINDOW_SMALL
+            {
+                Operation::StartBackref {
+                    count: count as u16,
+                    back: (pos + 2 - start) as u16,
+                }
+            } else {
+                Operation::StartBackref {
+                    count: count as u16,
+                    back: (pos + 3 - start) as u16,
+                }
+            };
+
+            let savings = count.saturating_sub(operation.encoded_len());
+            if savings > 0 && best.as_ref().is_none_or(|(best, _, _)| savings > *best) {
+                best = Some((savings, operation, taken + 1));
+            }
+        }
+
+        best.map(|(_, operation, taken)| (operation, taken))
+    }
+}
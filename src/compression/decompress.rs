@@ -1,16 +1,100 @@
-use super::{NibblePos, Operation};
-use thiserror::Error;
+use super::{NibblePos, Operation, TraceEvent, TracedOperation};
+use alloc::{string::String, vec::Vec};
+use core::fmt;
+#[cfg(feature = "std")]
+use std::io;
+
+mod fast;
+
+/// Bumped whenever the decoder changes what some stream decodes to, so results saved by an older
+/// version can be told apart.
+pub const DECODER_VERSION: u32 = 1;
+
+/// Limits and checks used while decompressing.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DecompressOptions {
+    /// decompression fails once the output grows past this size
+    pub max_output: usize,
+    /// how many bytes past the start the compressed data may reach, unlimited if `None`
+    pub max_input_span: Option<usize>,
+    pub loop_policy: LoopPolicy,
+    /// whether backrefs may re-read bytes in front of the start of the compressed data
+    pub allow_backref_before_start: bool,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LoopPolicy {
+    /// only rely on the output size and operation limits to stop
+    Off,
+    /// fail once the read index stayed the same for more than the given number of operations
+    SameIndex(usize),
+}
+
+impl Default for DecompressOptions {
+    fn default() -> Self {
+        Self {
+            max_output: 0x10000,
+            max_input_span: None,
+            loop_policy: LoopPolicy::SameIndex(0x100),
+            allow_backref_before_start: false,
+        }
+    }
+}
+
+impl DecompressOptions {
+    /// Settings that give up early, for running the decompressor on data that most likely
+    /// isn't compressed at all.
+    pub fn strict() -> Self {
+        Self {
+            max_output: 0x8000,
+            max_input_span: Some(0x8000),
+            loop_policy: LoopPolicy::SameIndex(0x10),
+            allow_backref_before_start: false,
+        }
+    }
+
+    /// Settings that only stop when decompression can't continue, for investigating
+    /// data the default settings reject.
+    pub fn permissive() -> Self {
+        Self {
+            max_output: 0x100000,
+            max_input_span: None,
+            loop_policy: LoopPolicy::Off,
+            allow_backref_before_start: true,
+        }
+    }
+
+    /// operations executed before giving up, enough for a backref in front of every output byte
+    fn max_operations(&self) -> usize {
+        self.max_output.saturating_mul(2).saturating_add(2)
+    }
+}
 
 #[derive(Debug, Clone)]
 pub struct Decompressor<'a> {
     src: &'a [u8],
     dst: Vec<u8>,
+    options: DecompressOptions,
+    /// bytes at the start of `dst` that were there before decompressing
+    history_len: usize,
+    /// bytes of the output already handed out by the iterator or reader
+    emitted: usize,
+    /// set once the exit was reached or an error occurred
+    finished: bool,
+    /// copy whole operations at once where possible, see [`Decompressor::decompress_fast`]
+    bulk: bool,
 
     /// used for detecting out of bounds errors
     start_index: usize,
     /// used for detecting loops in decompression
     prev_index: usize,
     loop_count: usize,
+    /// catches cycles of backrefs that don't produce any output
+    operation_count: usize,
+
+    /// opcode and operation currently being executed, for error context
+    opcode: Option<u8>,
+    operation: Option<Operation>,
 
     /// index to read from
     read_index: usize,
@@ -18,25 +102,124 @@ pub struct Decompressor<'a> {
     old_index: usize,
     /// bytes remaining in a backref
     backref_remaining: usize,
+
+    /// recorded events, only present when tracing
+    trace: Option<Vec<TraceEvent>>,
+    /// bytes read for the current operation when tracing
+    op_bytes: Vec<u8>,
+    /// backref events that happened during the current operation
+    pending_events: Vec<TraceEvent>,
 }
 
+#[derive(Debug)]
 pub struct DecompressResult {
     pub data: Vec<u8>,
     pub bytes_read: usize,
 }
 
-#[derive(Error, Debug)]
+#[derive(Debug)]
 pub enum DecompressError {
-    #[error("Data contained an invalid operation")]
-    InvalidOperation,
-    #[error("Loop detected in decompression")]
-    LoopDetected,
-    #[error("Maximum size exceeded")]
-    MaxSizeExceeded,
-    #[error("Invalid Data")]
-    InvalidData,
-    #[error("Decompressed data didn't match expected layout: {0}")]
-    InvalidLayout(String),
+    InvalidOperation(ErrorContext),
+    LoopDetected(ErrorContext),
+    MaxSizeExceeded(ErrorContext),
+    InvalidData(ErrorContext),
+    InputSpanExceeded(ErrorContext),
+    InvalidLayout {
+        message: String,
+        context: ErrorContext,
+    },
+}
+
+/// Where in the compressed data a [`DecompressError`] happened.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ErrorContext {
+    /// offset the compressed data starts at
+    pub region_start: usize,
+    /// read index at the time of the error
+    pub read_index: usize,
+    /// opcode of the failing operation, if it was read already
+    pub opcode: Option<u8>,
+    /// the failing operation, if it could be decoded
+    pub operation: Option<Operation>,
+    /// bytes written to the output before the error
+    pub bytes_produced: usize,
+}
+
+impl DecompressError {
+    pub fn invalid_layout(message: impl Into<String>) -> Self {
+        Self::InvalidLayout {
+            message: message.into(),
+            context: ErrorContext::default(),
+        }
+    }
+
+    /// What went wrong, without the [`ErrorContext`] of where it happened. Errors of the same
+    /// kind share it, so it can be used to group them.
+    pub fn summary(&self) -> &'static str {
+        match self {
+            Self::InvalidOperation(_) => "Data contained an invalid operation",
+            Self::LoopDetected(_) => "Loop detected in decompression",
+            Self::MaxSizeExceeded(_) => "Maximum size exceeded",
+            Self::InvalidData(_) => "Invalid Data",
+            Self::InputSpanExceeded(_) => "Compressed data is longer than allowed",
+            Self::InvalidLayout { .. } => "Decompressed data didn't match expected layout",
+        }
+    }
+
+    pub fn context(&self) -> &ErrorContext {
+        match self {
+            Self::InvalidOperation(context)
+            | Self::LoopDetected(context)
+            | Self::MaxSizeExceeded(context)
+            | Self::InvalidData(context)
+            | Self::InputSpanExceeded(context)
+            | Self::InvalidLayout { context, .. } => context,
+        }
+    }
+
+    pub fn context_mut(&mut self) -> &mut ErrorContext {
+        match self {
+            Self::InvalidOperation(context)
+            | Self::LoopDetected(context)
+            | Self::MaxSizeExceeded(context)
+            | Self::InvalidData(context)
+            | Self::InputSpanExceeded(context)
+            | Self::InvalidLayout { context, .. } => context,
+        }
+    }
+}
+
+impl fmt::Display for DecompressError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::InvalidLayout { message, context } => {
+                write!(f, "{}: {} {}", self.summary(), message, context)
+            }
+            _ => write!(f, "{} {}", self.summary(), self.context()),
+        }
+    }
+}
+
+impl core::error::Error for DecompressError {}
+
+impl fmt::Display for ErrorContext {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "at {:#07x} (data starting at {:#07x}",
+            self.read_index, self.region_start
+        )?;
+
+        match (self.opcode, &self.operation) {
+            (Some(opcode), Some(operation)) => {
+                write!(f, ", opcode {:#04x} {:?}", opcode, operation)?
+            }
+            (Some(opcode), None) => write!(f, ", opcode {:#04x}", opcode)?,
+            _ => {}
+        }
+
+        write!(f, ", {} bytes written)", self.bytes_produced)
+    }
 }
 
 impl Operation {
@@ -48,7 +231,7 @@ impl Operation {
             n @ 0x50..0x60 => Ok(Self::CopyDoubled(n & 0x0f)),
             n @ 0x60..0x80 => Ok(Self::CopyInterleaved {
                 count: (n & 0x0f) + 1,
-                fixed_value: decompressor.read()?,
+                fixed_value: decompressor.read_byte()?,
                 fixed_first: n < 0x70,
             }),
 
@@ -59,9 +242,10 @@ impl Operation {
             n @ 0xf0..0xf8 => Self::decode_repeat_value_small(n, decompressor),
 
             n @ 0xf8..0xfc => Self::decode_backref_large(n, decompressor),
-            n @ 0xfc..0xff => Self::decode_backref_small(n, decompressor),
+            n @ 0xfc..0xfe => Self::decode_backref_small(n, decompressor),
 
-            0xff => Ok(Self::Exit),
+            // the routine treats everything from 0xfe on as the end
+            0xfe..=0xff => Ok(Self::Exit),
         }
     }
 
@@ -71,20 +255,26 @@ impl Operation {
     ) -> Result<Self, DecompressError> {
         let count = (op & 0x0f) + 1;
 
-        let op_2 = decompressor.read()?;
-
-        let fixed_pos = if (op_2 & 0x10) == 0 {
-            NibblePos::Upper
-        } else {
-            NibblePos::Lower
-        };
+        let op_2 = decompressor.read_byte()?;
 
+        let fixed_pos;
         let fixed;
         let initial: Option<u8>;
         if op_2 < 0x80 {
+            // the routine only checks whether any of the upper bits are set here, not just bit 4
+            fixed_pos = if op_2 < 0x10 {
+                NibblePos::Upper
+            } else {
+                NibblePos::Lower
+            };
             initial = None;
             fixed = op_2 & 0x0f;
         } else {
+            fixed_pos = if (op_2 & 0x10) == 0 {
+                NibblePos::Upper
+            } else {
+                NibblePos::Lower
+            };
             initial = Some(op_2 & 0x0f);
             fixed = if (op_2 & 0x40) == 0 { 0x00 } else { 0x0f };
         }
@@ -114,7 +304,7 @@ impl Operation {
         decompressor: &mut Decompressor,
     ) -> Result<Self, DecompressError> {
         let upper = op & 0x1f;
-        let lower = decompressor.read()?;
+        let lower = decompressor.read_byte()?;
 
         let count = ((upper << 1) | (lower >> 7)) + 1;
         let back = u16::from_be_bytes([lower & 0x7f, decompressor.read_raw()?]);
@@ -136,7 +326,7 @@ impl Operation {
         decompressor: &mut Decompressor,
     ) -> Result<Self, DecompressError> {
         let upper = op & 0x0f;
-        let lower = decompressor.read()?;
+        let lower = decompressor.read_byte()?;
         let count = u16::from_be_bytes([upper, lower]) + 3;
 
         let value = decompressor.read_raw()?;
@@ -174,16 +364,25 @@ impl Operation {
 
         Ok(Self::StartBackref {
             count: count + 3,
-            back: back as u16,
+            back,
         })
     }
 }
 
 impl<'a> Decompressor<'a> {
     pub fn new(src: &'a [u8], y: usize) -> Self {
+        Self::with_options(src, y, DecompressOptions::default())
+    }
+
+    pub fn with_options(src: &'a [u8], y: usize, options: DecompressOptions) -> Self {
         Self {
             src,
             dst: Vec::new(),
+            options,
+            history_len: 0,
+            emitted: 0,
+            finished: false,
+            bulk: false,
 
             read_index: y,
             start_index: y,
@@ -192,55 +391,178 @@ impl<'a> Decompressor<'a> {
             old_index: 0,
             prev_index: 0,
             loop_count: 0,
+            operation_count: 0,
+
+            opcode: None,
+            operation: None,
+
+            trace: None,
+            op_bytes: Vec::new(),
+            pending_events: Vec::new(),
         }
     }
 
+    /// Treat `history` as if it was output right before the compressed data starts,
+    /// so backreads can reach into it. It isn't part of the decompressed data.
+    pub fn with_history(mut self, history: &[u8]) -> Self {
+        self.dst = history.to_vec();
+        self.history_len = history.len();
+        self
+    }
+
     pub fn decompress(mut self) -> Result<DecompressResult, DecompressError> {
-        loop {
-            let value = self.read()?;
-
-            let operation = Operation::decode(value, &mut self)?;
-
-            log::trace!("operation: {:?}", operation);
-
-            match operation {
-                Operation::CopySimple(count) => self.copy_simple(count)?,
-                Operation::CopyNibbleFixed {
-                    fixed,
-                    fixed_pos,
-                    count,
-                    initial,
-                } => self.copy_nibble_fixed(count, fixed, fixed_pos, initial)?,
-                Operation::CopyDoubled(count) => self.copy_doubled(count)?,
-                Operation::CopyInterleaved {
-                    count,
-                    fixed_value,
-                    fixed_first,
-                } => self.copy_interleaved(count, fixed_value, fixed_first)?,
-
-                Operation::CopyBackread { count, back } => {
-                    self.copy_backread(count, back)?;
-                }
+        self.run()?;
 
-                Operation::RepeatValue { count, value } => {
-                    self.repeat_value(count, value);
-                }
+        Ok(self.into_result())
+    }
 
-                Operation::StartBackref { count, back } => {
-                    self.start_backref(count, back)?;
-                }
+    /// Decompress while recording every executed operation and backref.
+    /// The trace is returned even if decompression fails, covering everything up to the error.
+    pub fn decompress_traced(
+        mut self,
+    ) -> (Vec<TraceEvent>, Result<DecompressResult, DecompressError>) {
+        self.trace = Some(Vec::new());
+        let result = self.run();
 
-                Operation::Exit => break,
+        let trace = self.trace.take().unwrap_or_default();
+        let result = result.map(|_| self.into_result());
+
+        (trace, result)
+    }
+
+    fn into_result(mut self) -> DecompressResult {
+        self.dst.drain(..self.history_len);
+
+        DecompressResult {
+            bytes_read: self.bytes_read(),
+            data: self.dst,
+        }
+    }
+
+    /// Bytes of compressed data read so far. After the iterator or reader ran out,
+    /// this is the length of the compressed data.
+    pub fn bytes_read(&self) -> usize {
+        self.read_index.saturating_sub(self.start_index)
+    }
+
+    /// bytes written by the decompressor so far
+    fn output_len(&self) -> usize {
+        self.dst.len() - self.history_len
+    }
+
+    fn run(&mut self) -> Result<(), DecompressError> {
+        while !self.finished {
+            self.step()?;
+        }
+
+        Ok(())
+    }
+
+    /// Execute a single operation.
+    fn step(&mut self) -> Result<(), DecompressError> {
+        match self.execute_operation() {
+            Ok(done) => {
+                self.finished = done;
+                Ok(())
+            }
+            Err(mut error) => {
+                self.finished = true;
+                *error.context_mut() = ErrorContext {
+                    region_start: self.start_index,
+                    read_index: self.read_index,
+                    opcode: self.opcode,
+                    operation: self.operation.clone(),
+                    bytes_produced: self.output_len(),
+                };
+                Err(error)
+            }
+        }
+    }
+
+    /// returns whether decompression is done
+    fn execute_operation(&mut self) -> Result<bool, DecompressError> {
+        let offset = self.read_index;
+        let output_start = self.output_len();
+        self.op_bytes.clear();
+        self.opcode = None;
+        self.operation = None;
+
+        let value = self.read_byte()?;
+        self.opcode = Some(value);
+
+        let operation = Operation::decode(value, self)?;
+        self.operation = Some(operation.clone());
+        let header_len = self.op_bytes.len();
+
+        log::trace!("operation: {:?}", operation);
+
+        match operation {
+            Operation::CopySimple(count) if self.bulk => self.copy_simple_bulk(count)?,
+            Operation::CopySimple(count) => self.copy_simple(count)?,
+            Operation::CopyNibbleFixed {
+                fixed,
+                fixed_pos,
+                count,
+                initial,
+            } => self.copy_nibble_fixed(count, fixed, fixed_pos, initial)?,
+            Operation::CopyDoubled(count) => self.copy_doubled(count)?,
+            Operation::CopyInterleaved {
+                count,
+                fixed_value,
+                fixed_first,
+            } => self.copy_interleaved(count, fixed_value, fixed_first)?,
+
+            Operation::CopyBackread { count, back } if self.bulk => {
+                self.copy_backread_bulk(count, back)?;
+            }
+            Operation::CopyBackread { count, back } => {
+                self.copy_backread(count, back)?;
+            }
+
+            Operation::RepeatValue { count, value } if self.bulk => {
+                self.repeat_value_bulk(count, value);
+            }
+            Operation::RepeatValue { count, value } => {
+                self.repeat_value(count, value);
             }
 
-            if self.dst.len() > 0x10000 {
-                return Err(DecompressError::MaxSizeExceeded);
+            Operation::StartBackref { count, back } => {
+                self.start_backref(count, back)?;
             }
 
+            Operation::Exit => {}
+        }
+
+        let output_end = self.output_len();
+        if let Some(trace) = &mut self.trace {
+            trace.push(TraceEvent::Operation(TracedOperation {
+                offset,
+                raw: self.op_bytes[..header_len].to_vec(),
+                payload: self.op_bytes[header_len..].to_vec(),
+                operation: operation.clone(),
+                output: output_start..output_end,
+            }));
+            trace.append(&mut self.pending_events);
+        }
+
+        if operation == Operation::Exit {
+            return Ok(true);
+        }
+
+        if self.output_len() > self.options.max_output {
+            return Err(DecompressError::MaxSizeExceeded(ErrorContext::default()));
+        }
+
+        self.operation_count += 1;
+        if self.operation_count > self.options.max_operations() {
+            return Err(DecompressError::LoopDetected(ErrorContext::default()));
+        }
+
+        if let LoopPolicy::SameIndex(max_repeats) = self.options.loop_policy {
             if self.prev_index == self.read_index {
                 self.loop_count += 1;
-                if self.loop_count > 0x100 {
-                    return Err(DecompressError::LoopDetected);
+                if self.loop_count > max_repeats {
+                    return Err(DecompressError::LoopDetected(ErrorContext::default()));
                 }
             } else {
                 self.loop_count = 0;
@@ -248,28 +570,30 @@ impl<'a> Decompressor<'a> {
             self.prev_index = self.read_index;
         }
 
-        Ok(DecompressResult {
-            data: self.dst,
-            bytes_read: self.read_index - self.start_index,
-        })
+        Ok(false)
     }
 
     fn read_raw(&mut self) -> Result<u8, DecompressError> {
-        let value = self.src[self.read_index];
-        self.read_index += 1;
-
-        if self.read_index >= self.src.len() {
-            return Err(DecompressError::InvalidData);
+        if let Some(max_input_span) = self.options.max_input_span {
+            if self.read_index >= self.start_index.saturating_add(max_input_span) {
+                return Err(DecompressError::InputSpanExceeded(ErrorContext::default()));
+            }
         }
 
-        if self.read_index == 0 {
-            unreachable!("y overflow (unknown subroutine at a149)");
+        let value = *self
+            .src
+            .get(self.read_index)
+            .ok_or(DecompressError::InvalidData(ErrorContext::default()))?;
+        self.read_index += 1;
+
+        if self.trace.is_some() {
+            self.op_bytes.push(value);
         }
 
         Ok(value)
     }
 
-    fn read(&mut self) -> Result<u8, DecompressError> {
+    fn read_byte(&mut self) -> Result<u8, DecompressError> {
         let value = self.read_raw();
         self.check_backref_end();
         value
@@ -277,6 +601,13 @@ impl<'a> Decompressor<'a> {
 
     fn check_backref_end(&mut self) {
         if self.backref_remaining == 1 {
+            if self.trace.is_some() {
+                self.pending_events.push(TraceEvent::BackrefReturn {
+                    from: self.read_index,
+                    to: self.old_index,
+                });
+            }
+
             self.read_index = self.old_index;
         }
 
@@ -284,6 +615,7 @@ impl<'a> Decompressor<'a> {
     }
 
     #[allow(dead_code)]
+    #[cfg(feature = "std")]
     fn print_dst(&self) {
         for chunk in self.dst.chunks(16) {
             for v in chunk {
@@ -295,7 +627,7 @@ impl<'a> Decompressor<'a> {
 
     fn copy_simple(&mut self, count: u8) -> Result<(), DecompressError> {
         for _ in 0..=count {
-            let value = self.read()?;
+            let value = self.read_byte()?;
             self.dst.push(value);
         }
 
@@ -304,7 +636,7 @@ impl<'a> Decompressor<'a> {
 
     fn copy_doubled(&mut self, count: u8) -> Result<(), DecompressError> {
         for _ in 0..=count {
-            let value = self.read()?;
+            let value = self.read_byte()?;
 
             self.dst.push(value);
             self.dst.push(value);
@@ -330,7 +662,7 @@ impl<'a> Decompressor<'a> {
             let nibble = if let Some(current) = current.take() {
                 current & 0x0f
             } else {
-                let value = self.read()?;
+                let value = self.read_byte()?;
                 current = Some(value);
                 value >> 4
             };
@@ -356,10 +688,10 @@ impl<'a> Decompressor<'a> {
             if fixed_first {
                 self.dst.push(fixed_value);
 
-                let read = self.read()?;
+                let read = self.read_byte()?;
                 self.dst.push(read);
             } else {
-                let read = self.read()?;
+                let read = self.read_byte()?;
                 self.dst.push(read);
 
                 self.dst.push(fixed_value);
@@ -370,12 +702,13 @@ impl<'a> Decompressor<'a> {
     }
 
     fn copy_backread(&mut self, count: u8, back: u16) -> Result<(), DecompressError> {
-        if self.dst.len() < back as usize || back == 0 {
-            return Err(DecompressError::InvalidOperation);
-        }
+        let start = match self.dst.len().checked_sub(back as usize) {
+            Some(start) if back != 0 => start,
+            _ => return Err(DecompressError::InvalidOperation(ErrorContext::default())),
+        };
 
-        for _ in 0..=count {
-            self.dst.push(self.dst[self.dst.len() - back as usize]);
+        for i in 0..=count as usize {
+            self.dst.push(self.dst[start + i]);
         }
 
         self.check_backref_end();
@@ -392,14 +725,69 @@ impl<'a> Decompressor<'a> {
     }
 
     fn start_backref(&mut self, count: u16, back: u16) -> Result<(), DecompressError> {
-        if self.read_index < back as usize || (self.read_index - back as usize) < self.start_index {
-            return Err(DecompressError::InvalidOperation);
-        }
+        let to = self
+            .read_index
+            .checked_sub(back as usize)
+            .filter(|&to| to >= self.start_index || self.options.allow_backref_before_start)
+            .ok_or(DecompressError::InvalidOperation(ErrorContext::default()))?;
 
         self.backref_remaining = count as usize;
         self.old_index = self.read_index;
-        self.read_index -= back as usize;
+        self.read_index = to;
+
+        if self.trace.is_some() {
+            self.pending_events.push(TraceEvent::BackrefStart {
+                from: self.old_index,
+                to: self.read_index,
+                count: count as usize,
+            });
+        }
 
         Ok(())
     }
 }
+
+/// Decompresses lazily, only executing as many operations as needed for the next byte.
+/// Iteration ends after the first error.
+impl Iterator for Decompressor<'_> {
+    type Item = Result<u8, DecompressError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        while self.emitted == self.output_len() {
+            if self.finished {
+                return None;
+            }
+
+            if let Err(error) = self.step() {
+                // drop whatever the failed operation wrote before it stopped
+                self.emitted = self.output_len();
+                return Some(Err(error));
+            }
+        }
+
+        let value = self.dst[self.history_len + self.emitted];
+        self.emitted += 1;
+        Some(Ok(value))
+    }
+}
+
+/// Decompresses lazily like the [`Iterator`] implementation,
+/// a [`DecompressError`] is returned as [`io::ErrorKind::InvalidData`].
+#[cfg(feature = "std")]
+impl io::Read for Decompressor<'_> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        while self.emitted == self.output_len() && !self.finished {
+            if let Err(error) = self.step() {
+                self.emitted = self.output_len();
+                return Err(io::Error::new(io::ErrorKind::InvalidData, error));
+            }
+        }
+
+        let available = &self.dst[self.history_len + self.emitted..];
+        let len = available.len().min(buf.len());
+        buf[..len].copy_from_slice(&available[..len]);
+        self.emitted += len;
+
+        Ok(len)
+    }
+}
@@ -0,0 +1,81 @@
+//! The decode path behind [`Decompressor::decompress_fast`]. Operations are copied as a whole
+//! instead of byte by byte. Whenever that isn't possible, e.g. because the operation reads from a
+//! backref or runs out of data, the byte-wise implementation takes over, so errors come out
+//! exactly the same.
+
+use super::{DecompressError, DecompressResult, Decompressor, ErrorContext};
+
+/// output reserved up front, most streams decompress to less than a bank
+const RESERVED_OUTPUT: usize = 0x10000;
+
+impl Decompressor<'_> {
+    /// Decompress like [`Decompressor::decompress`] with the same results, but faster.
+    pub fn decompress_fast(mut self) -> Result<DecompressResult, DecompressError> {
+        self.dst
+            .reserve(self.options.max_output.min(RESERVED_OUTPUT));
+        self.bulk = true;
+
+        self.run()?;
+
+        Ok(self.into_result())
+    }
+
+    /// Whether everything up to `end` can be read straight from the source.
+    fn contiguous_until(&self, end: usize) -> bool {
+        let within_span = self
+            .options
+            .max_input_span
+            .is_none_or(|span| end <= self.start_index.saturating_add(span));
+
+        self.backref_remaining == 0 && end <= self.src.len() && within_span
+    }
+
+    pub(super) fn copy_simple_bulk(&mut self, count: u8) -> Result<(), DecompressError> {
+        let end = self.read_index + count as usize + 1;
+        if !self.contiguous_until(end) {
+            return self.copy_simple(count);
+        }
+
+        self.dst.extend_from_slice(&self.src[self.read_index..end]);
+        self.read_index = end;
+
+        Ok(())
+    }
+
+    pub(super) fn repeat_value_bulk(&mut self, count: u16, value: u8) {
+        if self.backref_remaining != 0 {
+            return self.repeat_value(count, value);
+        }
+
+        self.dst.resize(self.dst.len() + count as usize, value);
+    }
+
+    pub(super) fn copy_backread_bulk(
+        &mut self,
+        count: u8,
+        back: u16,
+    ) -> Result<(), DecompressError> {
+        if self.backref_remaining != 0 {
+            return self.copy_backread(count, back);
+        }
+
+        let back = back as usize;
+        let mut from = match self.dst.len().checked_sub(back) {
+            Some(start) if back != 0 => start,
+            _ => return Err(DecompressError::InvalidOperation(ErrorContext::default())),
+        };
+
+        // when the copy overlaps its own output only `back` bytes exist ahead of it at a time,
+        // so it has to happen in chunks of that size
+        let mut remaining = count as usize + 1;
+        while remaining > 0 {
+            let len = remaining.min(back);
+            self.dst.extend_from_within(from..from + len);
+
+            from += len;
+            remaining -= len;
+        }
+
+        Ok(())
+    }
+}
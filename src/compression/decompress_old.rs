@@ -1,3 +1,38 @@
+//! Near-literal transcription of the decompression routine at $80:A116, kept as a reference for
+//! [`Decompressor`](super::Decompressor). Labels are named after the addresses in the ROM.
+//!
+//! The routine itself never checks anything, so a few guards were added that stop decoding
+//! where the real hardware would read or write garbage. Those return a [`Halt`] that is passed
+//! up through every jump.
+
+// every branch of the routine is kept as an explicit jump
+#![allow(clippy::needless_return)]
+
+/// output size after which decoding is stopped, same as in the new decompressor
+const MAX_OUTPUT: usize = 0x10000;
+/// a cycle of backrefs doesn't produce any output, so operations are capped as well
+const MAX_OPERATIONS: usize = 0x20000;
+
+/// Why the old decompressor stopped before reaching an exit.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Halt {
+    /// tried to read past the end of the source
+    OutOfBounds,
+    /// a backread reached before the start of the output
+    InvalidBackread,
+    /// a backref reached before the start of the source
+    InvalidBackref,
+    MaxSizeExceeded,
+    TooManyOperations,
+}
+
+pub struct OldDecompressResult {
+    /// everything written before exiting or halting
+    pub data: Vec<u8>,
+    pub bytes_read: usize,
+    pub halt: Option<Halt>,
+}
+
 #[derive(Default)]
 struct State<'a> {
     src: &'a [u8],
@@ -15,6 +50,7 @@ struct State<'a> {
     cnt85: usize,
 
     dst: Vec<u8>,
+    operations: usize,
 }
 
 impl<'a> State<'a> {
@@ -26,13 +62,26 @@ impl<'a> State<'a> {
         }
     }
 
-    fn read(&mut self) {
-        self.a = self.src[self.y];
+    fn read(&mut self) -> Result<(), Halt> {
+        self.a = *self.src.get(self.y).ok_or(Halt::OutOfBounds)?;
         self.y += 1;
+        Ok(())
+    }
 
-        if self.y == 0 {
-            unreachable!("y overflow (unknown subroutine at a149)");
+    fn push(&mut self, value: u8) -> Result<(), Halt> {
+        if self.dst.len() >= MAX_OUTPUT {
+            return Err(Halt::MaxSizeExceeded);
         }
+        self.dst.push(value);
+        Ok(())
+    }
+
+    fn push_back(&mut self, back: u16) -> Result<(), Halt> {
+        let value = match self.dst.len().checked_sub(back as usize) {
+            Some(index) if back != 0 => self.dst[index],
+            _ => return Err(Halt::InvalidBackread),
+        };
+        self.push(value)
     }
 
     fn load_unknown7f_16bit(&self) -> u16 {
@@ -100,15 +149,15 @@ impl<'a> State<'a> {
     }
 }
 
-pub fn decompress(src: &[u8], offset: usize) -> Vec<u8> {
-    stacker::grow(1024 * 1024 * 10, || decompress_inner(src, offset))
+pub fn decompress(src: &[u8], offset: usize) -> OldDecompressResult {
+    stacker::maybe_grow(64 * 1024, 1024 * 1024, || decompress_inner(src, offset))
 }
 
-pub fn decompress_inner(src: &[u8], offset: usize) -> Vec<u8> {
+fn decompress_inner(src: &[u8], offset: usize) -> OldDecompressResult {
     let mut state = State::new(src, offset);
 
-    fn d_a12b(s: &mut State) {
-        s.read();
+    fn d_a12b(s: &mut State) -> Result<(), Halt> {
+        s.read()?;
 
         let stack1 = s.a;
         if s.cnt85 != 0 {
@@ -116,7 +165,7 @@ pub fn decompress_inner(src: &[u8], offset: usize) -> Vec<u8> {
             s.a = stack1;
         }
 
-        return;
+        return Ok(());
     }
 
     fn d_a13d(s: &mut State) {
@@ -130,7 +179,7 @@ pub fn decompress_inner(src: &[u8], offset: usize) -> Vec<u8> {
         return;
     }
 
-    fn d_a14f(s: &mut State) {
+    fn d_a14f(s: &mut State) -> Result<(), Halt> {
         s.unknown7f = s.a;
         s.asl();
 
@@ -151,25 +200,25 @@ pub fn decompress_inner(src: &[u8], offset: usize) -> Vec<u8> {
         s.asl();
         s.a |= 0x0f;
 
-        s.dst.push(s.a);
+        s.push(s.a)?;
 
         s.a = 0x1f;
         return d_a1a0(s);
     }
 
-    fn d_a168(s: &mut State) {
+    fn d_a168(s: &mut State) -> Result<(), Halt> {
         s.a = s.unknown7f;
         s.a &= 0x0f;
         s.a |= 0xf0;
 
-        s.dst.push(s.a);
+        s.push(s.a)?;
 
         s.a = 0x0f;
 
         return d_a1a0(s);
     }
 
-    fn d_a176(s: &mut State) {
+    fn d_a176(s: &mut State) -> Result<(), Halt> {
         s.a &= 0x20;
 
         if s.a == 0 {
@@ -182,30 +231,30 @@ pub fn decompress_inner(src: &[u8], offset: usize) -> Vec<u8> {
         s.asl();
         s.asl();
 
-        s.dst.push(s.a);
+        s.push(s.a)?;
 
         s.a = 0x10;
 
         return d_a1a0(s);
     }
 
-    fn d_a188(s: &mut State) {
+    fn d_a188(s: &mut State) -> Result<(), Halt> {
         s.a = s.unknown7f;
         s.a &= 0x0f;
 
-        s.dst.push(s.a);
+        s.push(s.a)?;
 
         s.a = 0;
 
         return d_a1a0(s);
     }
 
-    fn d_a194(s: &mut State) {
+    fn d_a194(s: &mut State) -> Result<(), Halt> {
         s.a &= 0x0f;
         s.a += 1;
         s.unknown81 = s.a;
 
-        d_a12b(s);
+        d_a12b(s)?;
 
         if s.a >= 0x80 {
             return d_a14f(s);
@@ -214,7 +263,7 @@ pub fn decompress_inner(src: &[u8], offset: usize) -> Vec<u8> {
         return d_a1a0(s);
     }
 
-    fn d_a1a0(s: &mut State) {
+    fn d_a1a0(s: &mut State) -> Result<(), Halt> {
         if s.a < 0x10 {
             return d_a1cc(s);
         }
@@ -225,14 +274,14 @@ pub fn decompress_inner(src: &[u8], offset: usize) -> Vec<u8> {
         return d_a1a8(s);
     }
 
-    fn d_a1a8(s: &mut State) {
-        d_a12b(s);
+    fn d_a1a8(s: &mut State) -> Result<(), Halt> {
+        d_a12b(s)?;
 
         s.unknown80 = s.a;
         s.a &= 0xf0;
         s.a |= s.unknown7f;
 
-        s.dst.push(s.a);
+        s.push(s.a)?;
 
         let branch = s.unknown81 == 0;
         s.unknown81 = s.unknown81.wrapping_sub(1);
@@ -248,7 +297,7 @@ pub fn decompress_inner(src: &[u8], offset: usize) -> Vec<u8> {
 
         s.a |= s.unknown7f;
 
-        s.dst.push(s.a);
+        s.push(s.a)?;
 
         let branch = s.unknown81 >= 1;
         s.unknown81 = s.unknown81.wrapping_sub(1);
@@ -259,7 +308,7 @@ pub fn decompress_inner(src: &[u8], offset: usize) -> Vec<u8> {
         return d_a2a1(s);
     }
 
-    fn d_a1cc(s: &mut State) {
+    fn d_a1cc(s: &mut State) -> Result<(), Halt> {
         s.asl();
         s.asl();
         s.asl();
@@ -270,8 +319,8 @@ pub fn decompress_inner(src: &[u8], offset: usize) -> Vec<u8> {
         return d_a1d2(s);
     }
 
-    fn d_a1d2(s: &mut State) {
-        d_a12b(s);
+    fn d_a1d2(s: &mut State) -> Result<(), Halt> {
+        d_a12b(s)?;
         s.unknown80 = s.a;
 
         s.lsr();
@@ -281,7 +330,7 @@ pub fn decompress_inner(src: &[u8], offset: usize) -> Vec<u8> {
 
         s.a |= s.unknown7f;
 
-        s.dst.push(s.a);
+        s.push(s.a)?;
 
         let branch = s.unknown81 == 0;
         s.unknown81 = s.unknown81.wrapping_sub(1);
@@ -293,7 +342,7 @@ pub fn decompress_inner(src: &[u8], offset: usize) -> Vec<u8> {
         s.a &= 0x0f;
         s.a |= s.unknown7f;
 
-        s.dst.push(s.a);
+        s.push(s.a)?;
 
         let branch = s.unknown81 >= 1;
         s.unknown81 = s.unknown81.wrapping_sub(1);
@@ -304,11 +353,11 @@ pub fn decompress_inner(src: &[u8], offset: usize) -> Vec<u8> {
         return d_a2a1(s);
     }
 
-    fn d_a1f3(s: &mut State) {
+    fn d_a1f3(s: &mut State) -> Result<(), Halt> {
         return d_a2a1(s);
     }
 
-    fn d_a1f6(s: &mut State) {
+    fn d_a1f6(s: &mut State) -> Result<(), Halt> {
         if s.a < 0x50 {
             return d_a194(s);
         }
@@ -319,16 +368,16 @@ pub fn decompress_inner(src: &[u8], offset: usize) -> Vec<u8> {
         return d_a1fe(s);
     }
 
-    fn d_a1fe(s: &mut State) {
-        s.read();
+    fn d_a1fe(s: &mut State) -> Result<(), Halt> {
+        s.read()?;
         let stack1 = s.a;
         if s.cnt85 != 0 {
             d_a13d(s);
             s.a = stack1;
         }
 
-        s.dst.push(s.a);
-        s.dst.push(s.a);
+        s.push(s.a)?;
+        s.push(s.a)?;
 
         let branch = s.unknown81 > 0;
         s.unknown81 = s.unknown81.wrapping_sub(1);
@@ -339,7 +388,7 @@ pub fn decompress_inner(src: &[u8], offset: usize) -> Vec<u8> {
         return d_a2a1(s);
     }
 
-    fn d_a21e(s: &mut State) {
+    fn d_a21e(s: &mut State) -> Result<(), Halt> {
         s.lsr();
 
         //cmp $60
@@ -351,7 +400,7 @@ pub fn decompress_inner(src: &[u8], offset: usize) -> Vec<u8> {
 
         s.xba();
 
-        s.read();
+        s.read()?;
 
         let stack1 = s.a;
         if s.cnt85 != 0 {
@@ -375,9 +424,9 @@ pub fn decompress_inner(src: &[u8], offset: usize) -> Vec<u8> {
 
         loop {
             s.a = s.unknown7f;
-            s.dst.push(s.a);
+            s.push(s.a)?;
 
-            s.read();
+            s.read()?;
 
             let stack1 = s.a;
             if s.cnt85 != 0 {
@@ -385,7 +434,7 @@ pub fn decompress_inner(src: &[u8], offset: usize) -> Vec<u8> {
                 s.a = stack1;
             }
 
-            s.dst.push(s.a);
+            s.push(s.a)?;
 
             if s.unknown81 == 0 {
                 break;
@@ -396,17 +445,17 @@ pub fn decompress_inner(src: &[u8], offset: usize) -> Vec<u8> {
         return d_a2a1(s);
     }
 
-    fn d_a262(s: &mut State) {
+    fn d_a262(s: &mut State) -> Result<(), Halt> {
         loop {
-            s.read();
+            s.read()?;
 
             let stack1 = s.a;
             if s.cnt85 != 0 {
                 d_a13d(s);
                 s.a = stack1;
             }
-            s.dst.push(s.a);
-            s.dst.push(s.unknown7f);
+            s.push(s.a)?;
+            s.push(s.unknown7f)?;
 
             if s.unknown81 == 0 {
                 break;
@@ -417,7 +466,7 @@ pub fn decompress_inner(src: &[u8], offset: usize) -> Vec<u8> {
         return d_a2a1(s);
     }
 
-    fn d_a283(s: &mut State, neg: bool) {
+    fn d_a283(s: &mut State, neg: bool) -> Result<(), Halt> {
         if neg {
             return d_a21e(s);
         }
@@ -428,8 +477,8 @@ pub fn decompress_inner(src: &[u8], offset: usize) -> Vec<u8> {
         return d_a288(s);
     }
 
-    fn d_a288(s: &mut State) {
-        s.read();
+    fn d_a288(s: &mut State) -> Result<(), Halt> {
+        s.read()?;
 
         let stack1 = s.a;
         if s.cnt85 != 0 {
@@ -437,7 +486,7 @@ pub fn decompress_inner(src: &[u8], offset: usize) -> Vec<u8> {
             s.a = stack1;
         }
 
-        s.dst.push(s.a);
+        s.push(s.a)?;
 
         let overflow = s.unknown81 == 0;
         s.unknown81 = s.unknown81.wrapping_sub(1);
@@ -449,8 +498,17 @@ pub fn decompress_inner(src: &[u8], offset: usize) -> Vec<u8> {
     }
 
     // entry point
-    fn d_a2a1(s: &mut State) {
-        s.read();
+    fn d_a2a1(s: &mut State) -> Result<(), Halt> {
+        s.operations += 1;
+        if s.operations > MAX_OPERATIONS {
+            return Err(Halt::TooManyOperations);
+        }
+
+        stacker::maybe_grow(64 * 1024, 1024 * 1024, || d_a2a1_inner(s))
+    }
+
+    fn d_a2a1_inner(s: &mut State) -> Result<(), Halt> {
+        s.read()?;
 
         let stack1 = s.a;
         if s.cnt85 != 0 {
@@ -472,7 +530,7 @@ pub fn decompress_inner(src: &[u8], offset: usize) -> Vec<u8> {
         return d_a2b7(s);
     }
 
-    fn d_a2b7(s: &mut State) {
+    fn d_a2b7(s: &mut State) -> Result<(), Halt> {
         s.lsr();
 
         let mut tmp = s.a;
@@ -487,8 +545,8 @@ pub fn decompress_inner(src: &[u8], offset: usize) -> Vec<u8> {
         return d_a2c2(s);
     }
 
-    fn d_a2c2(s: &mut State) {
-        s.read();
+    fn d_a2c2(s: &mut State) -> Result<(), Halt> {
+        s.read()?;
 
         s.store_unknown7f_16bit();
 
@@ -497,7 +555,7 @@ pub fn decompress_inner(src: &[u8], offset: usize) -> Vec<u8> {
         let back = s.load_unknown7f_16bit();
 
         loop {
-            s.dst.push(s.dst[s.dst.len() - back as usize]);
+            s.push_back(back)?;
             if s.unknown81 == 0 {
                 break;
             }
@@ -507,7 +565,7 @@ pub fn decompress_inner(src: &[u8], offset: usize) -> Vec<u8> {
         return d_a2e3(s);
     }
 
-    fn d_a2e3(s: &mut State) {
+    fn d_a2e3(s: &mut State) -> Result<(), Halt> {
         if s.cnt85 != 0 {
             d_a13d(s);
         }
@@ -515,7 +573,7 @@ pub fn decompress_inner(src: &[u8], offset: usize) -> Vec<u8> {
         return d_a2a1(s);
     }
 
-    fn d_a2ec(s: &mut State) {
+    fn d_a2ec(s: &mut State) -> Result<(), Halt> {
         s.ror();
 
         if s.a >= 0xe0 {
@@ -525,7 +583,7 @@ pub fn decompress_inner(src: &[u8], offset: usize) -> Vec<u8> {
         s.a &= 0x1f;
         s.xba();
 
-        s.read();
+        s.read()?;
 
         let stack1 = s.a;
         if s.cnt85 != 0 {
@@ -542,7 +600,7 @@ pub fn decompress_inner(src: &[u8], offset: usize) -> Vec<u8> {
         return d_a2c2(s);
     }
 
-    fn d_a311(s: &mut State) {
+    fn d_a311(s: &mut State) -> Result<(), Halt> {
         if s.a >= 0xf0 {
             return d_a355(s);
         }
@@ -550,7 +608,7 @@ pub fn decompress_inner(src: &[u8], offset: usize) -> Vec<u8> {
         s.a &= 0x0f;
         s.unknown80 = s.a;
 
-        s.read();
+        s.read()?;
 
         let stack1 = s.a;
         if s.cnt85 != 0 {
@@ -560,7 +618,7 @@ pub fn decompress_inner(src: &[u8], offset: usize) -> Vec<u8> {
 
         s.unknown7f = s.a;
 
-        s.read();
+        s.read()?;
 
         let mut a_16 = s.load_unknown7f_16bit();
         a_16 += 3;
@@ -568,8 +626,8 @@ pub fn decompress_inner(src: &[u8], offset: usize) -> Vec<u8> {
         a_16 >>= 1;
 
         loop {
-            s.dst.push(s.a);
-            s.dst.push(s.a);
+            s.push(s.a)?;
+            s.push(s.a)?;
             a_16 -= 1;
             if a_16 == 0 {
                 break;
@@ -577,13 +635,13 @@ pub fn decompress_inner(src: &[u8], offset: usize) -> Vec<u8> {
         }
 
         if carry {
-            s.dst.push(s.a);
+            s.push(s.a)?;
         }
 
         return d_a2e3(s);
     }
 
-    fn d_a355(s: &mut State) {
+    fn d_a355(s: &mut State) -> Result<(), Halt> {
         if s.a >= 0xf8 {
             return d_a372(s);
         }
@@ -592,10 +650,10 @@ pub fn decompress_inner(src: &[u8], offset: usize) -> Vec<u8> {
         s.a += 2;
         s.unknown81 = s.a;
 
-        s.read();
+        s.read()?;
 
         loop {
-            s.dst.push(s.a);
+            s.push(s.a)?;
             if s.unknown81 == 0 {
                 break;
             }
@@ -605,7 +663,7 @@ pub fn decompress_inner(src: &[u8], offset: usize) -> Vec<u8> {
         return d_a2e3(s);
     }
 
-    fn d_a372(s: &mut State) {
+    fn d_a372(s: &mut State) -> Result<(), Halt> {
         if s.a >= 0xfc {
             return d_a3bf(s);
         }
@@ -613,7 +671,7 @@ pub fn decompress_inner(src: &[u8], offset: usize) -> Vec<u8> {
         s.a &= 0x03;
         s.xba();
 
-        s.read();
+        s.read()?;
 
         s.asl_16bit();
         s.asl_16bit();
@@ -627,7 +685,7 @@ pub fn decompress_inner(src: &[u8], offset: usize) -> Vec<u8> {
 
         let stack1 = s.a;
 
-        s.read();
+        s.read()?;
 
         let mut a_16 = s.load_a_16bit();
         a_16 += 3;
@@ -636,14 +694,15 @@ pub fn decompress_inner(src: &[u8], offset: usize) -> Vec<u8> {
         return d_a39b(s, stack1);
     }
 
-    fn d_a39b(s: &mut State, stack1: u8) {
+    fn d_a39b(s: &mut State, stack1: u8) -> Result<(), Halt> {
         s.unknown82 = s.y;
         s.store_unknown7f_16bit();
 
         // load $7e and store in $84 (why?)
 
-        let mut a_16 = s.y;
-        a_16 -= s.load_unknown7f_16bit() as usize;
+        let Some(a_16) = s.y.checked_sub(s.load_unknown7f_16bit() as usize) else {
+            return Err(Halt::InvalidBackref);
+        };
         // omitted code that i dont know how to handle at 0xa3ad
         s.y = a_16;
 
@@ -655,7 +714,7 @@ pub fn decompress_inner(src: &[u8], offset: usize) -> Vec<u8> {
         return d_a2a1(s);
     }
 
-    fn d_a3bf(s: &mut State) {
+    fn d_a3bf(s: &mut State) -> Result<(), Halt> {
         if s.a >= 0xfe {
             return d_a3e2(s);
         }
@@ -664,7 +723,7 @@ pub fn decompress_inner(src: &[u8], offset: usize) -> Vec<u8> {
 
         s.xba();
 
-        s.read();
+        s.read()?;
         s.asl_16bit();
         s.asl_16bit();
 
@@ -684,11 +743,15 @@ pub fn decompress_inner(src: &[u8], offset: usize) -> Vec<u8> {
         return d_a39b(s, stack1);
     }
 
-    fn d_a3e2(_: &mut State) {
-        return;
+    fn d_a3e2(_: &mut State) -> Result<(), Halt> {
+        return Ok(());
     }
 
-    d_a2a1(&mut state);
+    let halt = d_a2a1(&mut state).err();
 
-    state.dst
+    OldDecompressResult {
+        bytes_read: state.y.saturating_sub(offset),
+        data: state.dst,
+        halt,
+    }
 }
@@ -0,0 +1,121 @@
+//! Runs the old transcription of the decompression routine and the [`Decompressor`] on the same
+//! data and reports where they disagree.
+
+use super::{
+    decompress_old::{self, Halt},
+    DecompressError, Decompressor, TraceEvent, TracedOperation,
+};
+use rayon::prelude::*;
+
+#[derive(Debug)]
+pub struct Disagreement {
+    /// offset of the compressed data in the source
+    pub offset: usize,
+    /// first byte of the output the decoders disagree on
+    pub position: usize,
+    pub mismatch: Mismatch,
+    /// operation of the new decoder that wrote `position`, if it got that far
+    pub operation: Option<TracedOperation>,
+}
+
+#[derive(Debug)]
+pub enum Mismatch {
+    /// the decoders wrote different bytes, `None` if one of them stopped before `position`
+    Byte { old: Option<u8>, new: Option<u8> },
+    /// the output matched but the decoders stopped at different positions in the source
+    BytesRead { old: usize, new: usize },
+    /// the old decoder halted while the new one finished without an error
+    OldHalted(Halt),
+    /// the new decoder failed while the old one reached an exit
+    NewFailed(DecompressError),
+}
+
+/// Decode the data at `offset` with both decompressors, returning the first difference.
+/// Data both of them reject isn't considered a disagreement.
+pub fn compare(src: &[u8], offset: usize) -> Option<Disagreement> {
+    let old = decompress_old::decompress(src, offset);
+    let (trace, new) = Decompressor::new(src, offset).decompress_traced();
+
+    let operation_at = |position: usize| {
+        trace.iter().find_map(|event| match event {
+            TraceEvent::Operation(operation) if operation.output.contains(&position) => {
+                Some(operation.clone())
+            }
+            _ => None,
+        })
+    };
+
+    let (position, mismatch) = match (old.halt, new) {
+        (Some(_), Err(_)) => return None,
+
+        (None, Err(error)) => {
+            // the trace ends with the last operation that completed
+            let position = trace
+                .iter()
+                .rev()
+                .find_map(|event| match event {
+                    TraceEvent::Operation(operation) => Some(operation.output.end),
+                    _ => None,
+                })
+                .unwrap_or(0);
+
+            (position, Mismatch::NewFailed(error))
+        }
+
+        (halt, Ok(new)) => {
+            let position = old
+                .data
+                .iter()
+                .zip(&new.data)
+                .position(|(old, new)| old != new)
+                .unwrap_or(old.data.len().min(new.data.len()));
+
+            if position < old.data.len().max(new.data.len()) {
+                let byte = Mismatch::Byte {
+                    old: old.data.get(position).copied(),
+                    new: new.data.get(position).copied(),
+                };
+
+                match halt {
+                    // the old decoder just stopped early
+                    Some(halt) if position == old.data.len() => {
+                        (position, Mismatch::OldHalted(halt))
+                    }
+                    _ => (position, byte),
+                }
+            } else if let Some(halt) = halt {
+                (position, Mismatch::OldHalted(halt))
+            } else if old.bytes_read != new.bytes_read {
+                let mismatch = Mismatch::BytesRead {
+                    old: old.bytes_read,
+                    new: new.bytes_read,
+                };
+                (position, mismatch)
+            } else {
+                return None;
+            }
+        }
+    };
+
+    Some(Disagreement {
+        offset,
+        position,
+        operation: operation_at(position),
+        mismatch,
+    })
+}
+
+/// [`compare`] every offset of `src`, calling `progress` after each one.
+pub fn compare_all(src: &[u8], progress: impl Fn(usize) + Sync) -> Vec<Disagreement> {
+    let mut disagreements = (0..src.len())
+        .into_par_iter()
+        .filter_map(|offset| {
+            let result = compare(src, offset);
+            progress(offset);
+            result
+        })
+        .collect::<Vec<_>>();
+
+    disagreements.sort_by_key(|disagreement| disagreement.offset);
+    disagreements
+}
@@ -0,0 +1,141 @@
+//! Decompression the way the routine at $80:A116 sees memory: the source is read from a
+//! LoROM address through Y, the output is written to WRAM through DB:X.
+//!
+//! When Y wraps past $FFFF the subroutine at $A149 moves on to the next bank at $8000,
+//! which for LoROM is simply the next byte of the image. X only has 16 bits, so the
+//! output wraps around within its bank, and backreads can reach into whatever was in
+//! WRAM before decompression started.
+
+use super::{DecompressError, DecompressOptions, Decompressor};
+use crate::SnesAddress;
+use alloc::{boxed::Box, vec, vec::Vec};
+use core::fmt;
+
+const BANK_SIZE: usize = 0x10000;
+
+/// The 128KiB of work RAM in banks $7E and $7F.
+#[derive(Clone)]
+pub struct Wram(Box<[u8]>);
+
+#[derive(Debug)]
+pub enum EmulationError {
+    NotRom(SnesAddress),
+    NotWram(SnesAddress),
+    Decompress {
+        address: SnesAddress,
+        source: DecompressError,
+    },
+}
+
+impl fmt::Display for EmulationError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::NotRom(address) => write!(f, "{} is not mapped to ROM", address),
+            Self::NotWram(address) => write!(
+                f,
+                "{} is not in WRAM, only banks $7E and $7F are supported",
+                address
+            ),
+            Self::Decompress { address, .. } => {
+                write!(f, "Failed to decompress data at {}", address)
+            }
+        }
+    }
+}
+
+impl core::error::Error for EmulationError {
+    fn source(&self) -> Option<&(dyn core::error::Error + 'static)> {
+        match self {
+            Self::Decompress { source, .. } => Some(source),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct EmulatedResult {
+    /// everything written to WRAM, in order
+    pub data: Vec<u8>,
+    /// value of DB:Y after the exit was read
+    pub source_end: SnesAddress,
+    /// value of DB:X after the last byte was written
+    pub dest_end: SnesAddress,
+}
+
+impl Default for Wram {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Wram {
+    pub fn new() -> Self {
+        Self(vec![0; 2 * BANK_SIZE].into_boxed_slice())
+    }
+
+    /// The contents of bank $7E or $7F.
+    pub fn bank(&self, bank: u8) -> Option<&[u8]> {
+        let start = Self::bank_start(bank)?;
+        Some(&self.0[start..start + BANK_SIZE])
+    }
+
+    pub fn bank_mut(&mut self, bank: u8) -> Option<&mut [u8]> {
+        let start = Self::bank_start(bank)?;
+        Some(&mut self.0[start..start + BANK_SIZE])
+    }
+
+    pub fn data(&self) -> &[u8] {
+        &self.0
+    }
+
+    fn bank_start(bank: u8) -> Option<usize> {
+        match bank {
+            0x7e => Some(0),
+            0x7f => Some(BANK_SIZE),
+            _ => None,
+        }
+    }
+
+    /// Decompress the LoROM data at `source` into WRAM at `dest`.
+    pub fn decompress(
+        &mut self,
+        rom: &[u8],
+        source: SnesAddress,
+        dest: SnesAddress,
+        options: DecompressOptions,
+    ) -> Result<EmulatedResult, EmulationError> {
+        let offset = source
+            .lorom_offset()
+            .ok_or(EmulationError::NotRom(source))?;
+        let bank = self
+            .bank_mut(dest.bank)
+            .ok_or(EmulationError::NotWram(dest))?;
+
+        // the bank rotated so it ends right in front of X, backreads reaching in front of the
+        // output then read the same addresses they would in WRAM
+        let x = dest.addr as usize;
+        let history = [&bank[x..], &bank[..x]].concat();
+
+        let result = Decompressor::with_options(rom, offset, options)
+            .with_history(&history)
+            .decompress()
+            .map_err(|source_error| EmulationError::Decompress {
+                address: source,
+                source: source_error,
+            })?;
+
+        for (i, &value) in result.data.iter().enumerate() {
+            bank[(x + i) % BANK_SIZE] = value;
+        }
+
+        // keep the mirror the data was read through
+        let mut source_end = SnesAddress::from_lorom_offset(offset + result.bytes_read);
+        source_end.bank |= source.bank & 0x80;
+
+        Ok(EmulatedResult {
+            source_end,
+            dest_end: SnesAddress::new(dest.bank, dest.addr.wrapping_add(result.data.len() as u16)),
+            data: result.data,
+        })
+    }
+}
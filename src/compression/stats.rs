@@ -0,0 +1,136 @@
+//! Numbers describing how a compressed stream was put together, useful for comparing our
+//! encoder with the one used for the original data.
+
+use super::{DecompressResult, Operation, TraceEvent};
+use alloc::collections::BTreeMap;
+use core::fmt;
+#[cfg(feature = "std")]
+use serde::Serialize;
+
+/// An [`Operation`] without its arguments.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+#[cfg_attr(feature = "std", derive(Serialize), serde(rename_all = "kebab-case"))]
+pub enum OperationKind {
+    CopySimple,
+    CopyNibbleFixed,
+    CopyDoubled,
+    CopyInterleaved,
+    CopyBackread,
+    RepeatValue,
+    StartBackref,
+    Exit,
+}
+
+impl OperationKind {
+    pub const ALL: [OperationKind; 8] = [
+        OperationKind::CopySimple,
+        OperationKind::CopyNibbleFixed,
+        OperationKind::CopyDoubled,
+        OperationKind::CopyInterleaved,
+        OperationKind::CopyBackread,
+        OperationKind::RepeatValue,
+        OperationKind::StartBackref,
+        OperationKind::Exit,
+    ];
+}
+
+/// Uses the mnemonics of the [`assemble`](super::assemble) syntax.
+impl fmt::Display for OperationKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            OperationKind::CopySimple => write!(f, "copy"),
+            OperationKind::CopyNibbleFixed => write!(f, "nibble"),
+            OperationKind::CopyDoubled => write!(f, "double"),
+            OperationKind::CopyInterleaved => write!(f, "interleave"),
+            OperationKind::CopyBackread => write!(f, "backread"),
+            OperationKind::RepeatValue => write!(f, "repeat"),
+            OperationKind::StartBackref => write!(f, "backref"),
+            OperationKind::Exit => write!(f, "exit"),
+        }
+    }
+}
+
+impl Operation {
+    pub fn kind(&self) -> OperationKind {
+        match self {
+            Operation::CopySimple(_) => OperationKind::CopySimple,
+            Operation::CopyNibbleFixed { .. } => OperationKind::CopyNibbleFixed,
+            Operation::CopyDoubled(_) => OperationKind::CopyDoubled,
+            Operation::CopyInterleaved { .. } => OperationKind::CopyInterleaved,
+            Operation::CopyBackread { .. } => OperationKind::CopyBackread,
+            Operation::RepeatValue { .. } => OperationKind::RepeatValue,
+            Operation::StartBackref { .. } => OperationKind::StartBackref,
+            Operation::Exit => OperationKind::Exit,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default, PartialEq)]
+#[cfg_attr(feature = "std", derive(Serialize))]
+pub struct CompressionStats {
+    /// bytes of compressed data read
+    pub compressed_size: usize,
+    pub decompressed_size: usize,
+    /// how often each kind of operation was executed, including the ones re-read by a backref
+    pub operations: BTreeMap<OperationKind, usize>,
+    /// largest distance a `CopyBackread` reached back into the output
+    pub longest_backread: usize,
+    /// largest distance a `StartBackref` moved the read index back
+    pub longest_backref: usize,
+    /// `CopyBackread` operations that were read from inside a backref
+    pub nested_backreads: usize,
+    /// bytes of the output copied from the stream as-is by `CopySimple`
+    pub literal_bytes: usize,
+}
+
+impl CompressionStats {
+    /// Collect the statistics of a stream from its trace, see
+    /// [`Decompressor::decompress_traced`](super::Decompressor::decompress_traced).
+    pub fn from_trace(trace: &[TraceEvent], result: &DecompressResult) -> Self {
+        let mut stats = CompressionStats {
+            compressed_size: result.bytes_read,
+            decompressed_size: result.data.len(),
+            ..Default::default()
+        };
+
+        let mut in_backref = false;
+        for event in trace {
+            match event {
+                TraceEvent::Operation(traced) => {
+                    *stats.operations.entry(traced.operation.kind()).or_default() += 1;
+
+                    match traced.operation {
+                        Operation::CopySimple(_) => stats.literal_bytes += traced.output.len(),
+                        Operation::CopyBackread { back, .. } => {
+                            stats.longest_backread = stats.longest_backread.max(back as usize);
+                            if in_backref {
+                                stats.nested_backreads += 1;
+                            }
+                        }
+                        Operation::StartBackref { back, .. } => {
+                            stats.longest_backref = stats.longest_backref.max(back as usize);
+                        }
+                        _ => {}
+                    }
+                }
+                TraceEvent::BackrefStart { .. } => in_backref = true,
+                TraceEvent::BackrefReturn { .. } => in_backref = false,
+            }
+        }
+
+        stats
+    }
+
+    /// Compressed size relative to the decompressed size, smaller is better.
+    pub fn ratio(&self) -> f64 {
+        if self.decompressed_size == 0 {
+            return 0.0;
+        }
+
+        self.compressed_size as f64 / self.decompressed_size as f64
+    }
+
+    pub fn count(&self, kind: OperationKind) -> usize {
+        self.operations.get(&kind).copied().unwrap_or(0)
+    }
+}
@@ -0,0 +1,39 @@
+use super::Operation;
+use alloc::vec::Vec;
+use core::ops::Range;
+
+/// A single step recorded by [`Decompressor::decompress_traced`](super::Decompressor::decompress_traced).
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum TraceEvent {
+    /// An operation was decoded and executed.
+    Operation(TracedOperation),
+
+    /// A `StartBackref` moved the read index back to re-read `count` earlier bytes.
+    BackrefStart {
+        /// read index right after the `StartBackref` operation
+        from: usize,
+        /// read index the backref continues reading at
+        to: usize,
+        count: usize,
+    },
+
+    /// All bytes of a backref were read and the read index returned to where it was.
+    BackrefReturn {
+        /// read index right after the last byte of the backref
+        from: usize,
+        to: usize,
+    },
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TracedOperation {
+    /// offset of the opcode in the source
+    pub offset: usize,
+    /// the opcode and its arguments as read from the source
+    pub raw: Vec<u8>,
+    /// data copied from the source by the operation
+    pub payload: Vec<u8>,
+    pub operation: Operation,
+    /// range of the output written by the operation
+    pub output: Range<usize>,
+}
@@ -1,12 +1,49 @@
+#![cfg_attr(not(feature = "std"), no_std)]
+
+extern crate alloc;
+
 mod compression;
-pub use compression::{Compressable, DecompressError, Decompressor};
+pub use compression::{
+    assemble, disassemble, AssembleError, ChainEnd, ChainTable, Compressable, CompressionMode,
+    CompressionStats, Compressor, DecompressError, DecompressOptions, DecompressResult,
+    Decompressor, EmulatedResult, EmulationError, ErrorContext, LoopPolicy, NibblePos, Operation,
+    OperationKind, TraceEvent, TracedOperation, Wram, DECODER_VERSION,
+};
+#[cfg(feature = "decompress-old")]
+pub use compression::{
+    compare, compare_all, decompress_old, Disagreement, Halt, Mismatch, OldDecompressResult,
+};
 
 mod palette;
-pub use palette::{Palette, PaletteCollection, PaletteIndex, BW_PALETTE};
+pub use palette::{
+    Color, ColorIndex, Palette, PaletteCollection, PaletteData, PaletteIndex, BW_PALETTE,
+};
 mod tile;
 pub use tile::{PartialTileSet, Tile, TileMap, TileMapEntry, TileSet};
 mod sprite;
 pub use sprite::Sprite;
 
+mod address;
+pub use address::{ParseAddressError, SnesAddress};
+
+#[cfg(feature = "std")]
 mod rom;
-pub use rom::{MappedRom, Rom, RomError, RomMap};
+#[cfg(feature = "std")]
+pub use rom::{
+    FreeSpace, Insertion, IpsBase, MapMode, MapRegion, MapTarget, MappedRom, Pointer, RegionKind,
+    RegionStats, Rom, RomError, RomMap, SnesHeader, FILL_BYTE,
+};
+#[cfg(feature = "std")]
+mod patch;
+#[cfg(feature = "std")]
+pub use patch::{PatchError, PatchFormat};
+#[cfg(feature = "std")]
+mod scan;
+#[cfg(feature = "std")]
+pub use scan::{
+    deduplicate, draft_map, scan, scan_cached, Classifier, ScanCache, ScanHit, ScanResult, Score,
+};
+#[cfg(feature = "std")]
+mod sheet;
+#[cfg(feature = "std")]
+pub use sheet::TileSheet;
@@ -1,12 +1,60 @@
 use anyhow::Context;
 use clap::{Args, Parser, Subcommand};
-use std::{fs, path::PathBuf};
-use thanatos::{Compressable, MappedRom, Rom, RomMap};
+use std::{
+    fs,
+    path::{Path, PathBuf},
+    sync::Arc,
+};
+use thanatos::{Compressable, IpsBase, MapTarget, MappedRom, RegionKind, Rom, RomMap};
 
 #[derive(Parser, Debug)]
 struct Arguments {
     #[command(subcommand)]
     command: Commands,
+
+    /// Apply an IPS or BPS patch to the ROM after opening it. Can be given multiple times to
+    /// apply patches in order
+    #[arg(long = "patch", global = true)]
+    patches: Vec<PathBuf>,
+
+    /// Whether ROM maps are made for the ROM before or after the patches were applied
+    #[arg(long, global = true, default_value = "base")]
+    map_target: MapTargetArg,
+
+    /// Whether IPS patches for a ROM with a copier header were made with or without it. They
+    /// are refused for such ROMs unless this is given
+    #[arg(long, global = true)]
+    ips_base: Option<IpsBaseArg>,
+}
+
+#[derive(Debug, Clone, Copy, clap::ValueEnum)]
+enum MapTargetArg {
+    Base,
+    Patched,
+}
+
+#[derive(Debug, Clone, Copy, clap::ValueEnum)]
+enum IpsBaseArg {
+    Stripped,
+    Headered,
+}
+
+impl From<IpsBaseArg> for IpsBase {
+    fn from(value: IpsBaseArg) -> Self {
+        match value {
+            IpsBaseArg::Stripped => IpsBase::Stripped,
+            IpsBaseArg::Headered => IpsBase::Headered,
+        }
+    }
+}
+
+impl From<MapTargetArg> for MapTarget {
+    fn from(value: MapTargetArg) -> Self {
+        match value {
+            MapTargetArg::Base => MapTarget::Base,
+            MapTargetArg::Patched => MapTarget::Patched,
+        }
+    }
 }
 
 #[derive(Subcommand, Debug)]
@@ -31,50 +79,166 @@ enum Commands {
         #[command(flatten)]
         args: ScanArgs,
     },
+
+    /// Re-encode every region in the ROM map and check whether it comes back identical to the
+    /// data in the ROM
+    VerifyCompression {
+        rom: PathBuf,
+
+        /// Supply a custom ROM map that provides the offsets of the palettes and sprites
+        #[arg(short = 'm', long)]
+        rom_map: Option<PathBuf>,
+    },
+
+    /// Decode every region in the ROM map and report how its data was compressed
+    Stats {
+        rom: PathBuf,
+
+        /// Supply a custom ROM map that provides the offsets of the palettes and sprites
+        #[arg(short = 'm', long)]
+        rom_map: Option<PathBuf>,
+
+        #[arg(short, long, default_value = "table")]
+        format: StatsFormat,
+    },
+
+    /// Show the internal header of the ROM and check its checksum
+    Info { rom: PathBuf },
+
+    /// Compress decompressed data and write it back over a region of the ROM, saving the changes
+    /// as IPS and BPS patches. Data that doesn't fit anymore is moved to free space and the
+    /// pointers to it given with --pointer or --short-pointer are changed
+    Insert {
+        rom: PathBuf,
+
+        #[command(flatten)]
+        args: InsertArgs,
+    },
+
+    /// Work with IPS and BPS patches
+    Patch {
+        #[command(subcommand)]
+        command: PatchCommand,
+    },
+
+    /// List every operation decoded from the compressed data at the given offset
+    Explain {
+        rom: PathBuf,
+
+        /// Offset of the compressed data, e.g. 0x8ddbb
+        #[arg(value_parser = parse_offset)]
+        offset: usize,
+
+        /// Only stop decompressing when it can't continue, instead of applying the usual limits
+        #[arg(long)]
+        permissive: bool,
+    },
+
+    /// Decompress every offset of the ROM with both the old and the new decompressor and list
+    /// where they disagree
+    #[cfg(feature = "decompress-old")]
+    Differential { rom: PathBuf },
+}
+
+#[derive(Subcommand, Debug)]
+enum PatchCommand {
+    /// Write IPS and BPS patches with the changes between two ROMs, e.g. one modified by other
+    /// tools
+    Create {
+        original: PathBuf,
+        modified: PathBuf,
+
+        /// Where to write the patches, the extension is replaced. Defaults to the path of the
+        /// modified ROM
+        #[arg(short, long)]
+        out: Option<PathBuf>,
+    },
 }
 
 pub struct LoadedRom<'rom> {
     pub rom: Rom<'rom>,
+    pub map: Option<Arc<RomMap>>,
     pub mapped: Option<MappedRom>,
 }
 
 impl Commands {
-    fn get_rom(&self) -> anyhow::Result<LoadedRom<'_>> {
+    fn get_rom(
+        &self,
+        patches: &[PathBuf],
+        ips_base: Option<IpsBase>,
+        map_target: MapTarget,
+    ) -> anyhow::Result<LoadedRom<'_>> {
         let rom_path = match self {
             Commands::Export { rom, .. } => rom,
             Commands::Scan { rom, .. } => rom,
+            Commands::VerifyCompression { rom, .. } => rom,
+            Commands::Stats { rom, .. } => rom,
+            Commands::Info { rom } => rom,
+            Commands::Insert { rom, .. } => rom,
+            Commands::Patch {
+                command: PatchCommand::Create { original, .. },
+            } => original,
+            Commands::Explain { rom, .. } => rom,
+            #[cfg(feature = "decompress-old")]
+            Commands::Differential { rom } => rom,
         };
 
-        let rom = Rom::open(rom_path)?;
+        let mut rom = Rom::open_patched(rom_path, patches, ips_base)?;
+        rom.set_map_target(map_target);
+        if !patches.is_empty() {
+            log::info!(
+                "Applied {} patch(es), CRC {:#010x} -> {:#010x}",
+                patches.len(),
+                rom.base_crc(),
+                rom.crc()
+            );
+        }
 
-        let mapped = match self {
-            Commands::Export { rom_map, .. } => {
+        let (map, mapped) = match self {
+            Commands::Export { rom_map, .. } | Commands::VerifyCompression { rom_map, .. } => {
                 if let Some(rom_map) = rom_map {
-                    let map = RomMap::parse(&fs::read_to_string(rom_map)?)?;
+                    let map = Arc::new(RomMap::parse(&fs::read_to_string(rom_map)?)?);
 
                     if map.is_compatible_with(&rom) {
-                        Some(MappedRom::new(&rom, &map)?)
+                        (Some(map.clone()), Some(MappedRom::new(&rom, &map)?))
                     } else {
                         log::warn!(
                             "ROM map is not compatible with the supplied ROM. Continuing anyway."
                         );
 
-                        Some(MappedRom::new_forced(&rom, &map)?)
+                        (Some(map.clone()), Some(MappedRom::new_forced(&rom, &map)?))
                     }
                 } else {
                     let map = RomMap::find_inbuilt_for(&rom).with_context(|| {
                         "Failed to find compatible ROM map for the supplied ROM"
                     })?;
 
-                    Some(MappedRom::new(&rom, &map)?)
+                    (Some(map.clone()), Some(MappedRom::new(&rom, &map)?))
                 }
             }
-            Commands::Scan { .. } => {
-                RomMap::find_inbuilt_for(&rom).and_then(|map| MappedRom::new(&rom, &map).ok())
+            // only decodes the regions, so it doesn't need the data to fit the definitions
+            Commands::Stats { rom_map, .. } => {
+                let map = match rom_map {
+                    Some(rom_map) => Arc::new(RomMap::parse(&fs::read_to_string(rom_map)?)?),
+                    None => RomMap::find_inbuilt_for(&rom).with_context(|| {
+                        "Failed to find compatible ROM map for the supplied ROM"
+                    })?,
+                };
+
+                (Some(map), None)
+            }
+            #[cfg(feature = "decompress-old")]
+            Commands::Differential { .. } => (None, None),
+            Commands::Insert { .. } | Commands::Patch { .. } => (None, None),
+            Commands::Info { .. } => (RomMap::find_inbuilt_for(&rom), None),
+            Commands::Scan { .. } | Commands::Explain { .. } => {
+                let map = RomMap::find_inbuilt_for(&rom);
+                let mapped = map.as_ref().and_then(|map| MappedRom::new(&rom, map).ok());
+                (map, mapped)
             }
         };
 
-        Ok(LoadedRom { rom, mapped })
+        Ok(LoadedRom { rom, map, mapped })
     }
 }
 
@@ -82,7 +246,11 @@ fn main() -> anyhow::Result<()> {
     let args = Arguments::parse();
     colog::init();
 
-    let rom = args.command.get_rom()?;
+    let rom = args.command.get_rom(
+        &args.patches,
+        args.ips_base.map(Into::into),
+        args.map_target.into(),
+    )?;
     if let Some(mapped) = &rom.mapped {
         log::info!(
             "Loaded ROM: '{}' with CRC: {:#08x}",
@@ -95,7 +263,22 @@ fn main() -> anyhow::Result<()> {
         Commands::Export { args, .. } => {
             export(rom, args.clone())?;
         }
-        Commands::Scan { args, .. } => scan(rom, args.clone())?,
+        Commands::Scan {
+            rom: rom_path,
+            args,
+        } => scan(rom, rom_path, args.clone())?,
+        Commands::VerifyCompression { .. } => verify_compression(rom)?,
+        Commands::Stats { format, .. } => stats(rom, format.clone())?,
+        Commands::Info { .. } => info(rom),
+        Commands::Insert { args, .. } => insert(rom, args)?,
+        Commands::Patch {
+            command: PatchCommand::Create { modified, out, .. },
+        } => create_patches(rom, modified, out.as_deref().unwrap_or(modified))?,
+        Commands::Explain {
+            offset, permissive, ..
+        } => explain(rom, *offset, *permissive)?,
+        #[cfg(feature = "decompress-old")]
+        Commands::Differential { .. } => differential(rom)?,
     }
 
     /*
@@ -133,6 +316,15 @@ fn main() -> anyhow::Result<()> {
     Ok(())
 }
 
+fn parse_offset(value: &str) -> Result<usize, String> {
+    let result = match value.strip_prefix("0x") {
+        Some(hex) => usize::from_str_radix(hex, 16),
+        None => value.parse(),
+    };
+
+    result.map_err(|e| format!("invalid offset '{}': {}", value, e))
+}
+
 #[derive(Args, Debug, Clone)]
 struct ExportArgs {
     /// The output directory to export the sprites and palettes to
@@ -208,7 +400,7 @@ fn export(rom: LoadedRom, args: ExportArgs) -> anyhow::Result<()> {
 
 #[derive(Args, Debug, Clone)]
 struct ScanArgs {
-    /// The output directory to export found sprites to
+    /// The output directory to export found data to
     #[arg(short, long)]
     out_dir: Option<PathBuf>,
 
@@ -216,20 +408,73 @@ struct ScanArgs {
     #[arg(short, long)]
     force: bool,
 
-    /// The minimum number of tiles to consider a valid tile set
-    /// The default of 128 might miss a few tile sets but it doesnt return a lot of garbage either,
-    /// using 64 is recommended if you want to find all tile sets
-    #[arg(short, long, default_value = "128")]
-    threshold: usize,
+    /// Only keep streams scoring at least this much, from 0 for garbage to 1 for data that is
+    /// very likely real
+    #[arg(long, default_value = "0.5")]
+    min_score: f64,
+
+    /// The order the found streams are listed in
+    #[arg(long, default_value = "offset")]
+    sort: ScanOrder,
+
+    /// Also export streams that don't look like palettes, tile sets or tile maps
+    #[arg(long)]
+    unknown: bool,
+
+    /// Separate the tiles on tile sheets by grid lines
+    #[arg(long)]
+    grid: bool,
+
+    /// Print the index of every tile on tile sheets
+    #[arg(long)]
+    labels: bool,
+
+    /// Write a draft ROM map listing the found palettes, tile sets and tile maps
+    #[arg(long)]
+    emit_map: Option<PathBuf>,
+
+    /// Where to keep the streams found, so later scans of the same ROM can reuse them and
+    /// interrupted ones continue where they stopped. Defaults to scan_<crc>.cache
+    #[arg(long)]
+    cache: Option<PathBuf>,
+
+    /// Scan the whole ROM without reading or writing a cache
+    #[arg(long, conflicts_with = "cache")]
+    no_cache: bool,
 }
 
-fn scan(rom: LoadedRom, args: ScanArgs) -> anyhow::Result<()> {
+/// An entry of `scan.json`.
+#[derive(serde::Serialize)]
+struct ScanEntry<'a> {
+    offset: usize,
+    /// the CPU address of the offset, according to the mapping of the ROM
+    address: String,
+    end: usize,
+    length: usize,
+    kind: Option<RegionKind>,
+    score: f64,
+    signals: &'a thanatos::Score,
+    /// the palette or tile sheet, or the listing of a tile map, relative to the output directory
+    sheet: Option<PathBuf>,
+    /// later offsets decoding to the tail of the same stream
+    aliases: &'a [usize],
+}
+
+#[derive(Debug, Clone, clap::ValueEnum)]
+enum ScanOrder {
+    Offset,
+
+    /// Highest score first
+    Score,
+}
+
+fn scan(rom: LoadedRom, rom_path: &Path, args: ScanArgs) -> anyhow::Result<()> {
     use indicatif::ProgressBar;
-    use rayon::prelude::*;
-    use std::sync::atomic::{AtomicUsize, Ordering};
-    use thanatos::{Decompressor, PartialTileSet};
+    use std::collections::BTreeMap;
+    use std::io::{self, Write};
+    use thanatos::{Classifier, DecompressOptions, ScanCache, ScanResult, TileSheet};
 
-    log::info!("Scanning entire ROM for tiles...");
+    log::info!("Scanning entire ROM for compressed data...");
 
     let out_dir = args
         .out_dir
@@ -240,72 +485,715 @@ fn scan(rom: LoadedRom, args: ScanArgs) -> anyhow::Result<()> {
     }
     fs::create_dir(&out_dir).with_context(|| "Failed to create output directory")?;
 
-    let rom = rom.rom;
+    let data = rom.rom.data();
+    let options = DecompressOptions::strict();
+    let sheet = TileSheet {
+        grid: args.grid,
+        labels: args.labels,
+        ..Default::default()
+    };
+
+    // written as results come in, so a scan that was cut short still leaves a report of what got
+    // exported until then
+    let report_path = out_dir.join("scan.json");
+    let mut report = io::BufWriter::new(
+        fs::File::create(&report_path).with_context(|| "Failed to create report")?,
+    );
+    write!(report, "[")?;
+    let mut entries = Vec::new();
+    let mut found = BTreeMap::new();
+    let mut failed = None;
+
+    let progress = ProgressBar::new(data.len() as u64);
+    let progress_fn = |done| progress.inc(done as u64);
+    let on_result = |result: &ScanResult| {
+        if result.score.value() < args.min_score || failed.is_some() {
+            return;
+        }
+
+        let kind = result
+            .kind
+            .map_or_else(|| "unknown".to_string(), |kind| kind.to_string());
+        *found.entry(kind).or_insert(0) += 1;
+
+        if result.kind.is_none() && !args.unknown {
+            return;
+        }
+
+        let entry = progress
+            .suspend(|| export_scan_result(&rom.rom, result, &out_dir, &sheet))
+            .and_then(|entry| {
+                write!(
+                    report,
+                    "{}\n  {}",
+                    if entries.is_empty() { "" } else { "," },
+                    entry
+                )?;
+                report.flush()?;
+                Ok(entry)
+            });
+
+        match entry {
+            Ok(entry) => entries.push((result.score.value(), entry)),
+            Err(error) => failed = Some(error),
+        }
+    };
+
+    let results = if args.no_cache {
+        thanatos::scan(
+            data,
+            &options,
+            &Classifier::default(),
+            progress_fn,
+            on_result,
+        )
+    } else {
+        let path = args
+            .cache
+            .clone()
+            .unwrap_or_else(|| format!("scan_{:08x}.cache", rom.rom.crc()).into());
+        let mut cache = ScanCache::open(&path, rom.rom.crc(), &options)
+            .with_context(|| format!("Failed to open scan cache {}", path.display()))?;
+
+        thanatos::scan_cached(
+            data,
+            &options,
+            &Classifier::default(),
+            &mut cache,
+            progress_fn,
+            on_result,
+        )
+        .with_context(|| format!("Failed to write scan cache {}", path.display()))?
+    };
+    progress.finish_and_clear();
+
+    if let Some(error) = failed {
+        return Err(error);
+    }
+
+    writeln!(report, "\n]")?;
+    report.flush()?;
+    drop(report);
+
+    if let ScanOrder::Score = args.sort {
+        // stable, so equal scores stay in offset order
+        entries.sort_by(|a, b| b.0.total_cmp(&a.0));
+        let entries = entries
+            .iter()
+            .map(|(_, entry)| entry.as_str())
+            .collect::<Vec<_>>();
+        fs::write(&report_path, format!("[\n  {}\n]\n", entries.join(",\n  ")))
+            .with_context(|| "Failed to write report")?;
+    }
+
+    let results = results
+        .into_iter()
+        .filter(|result| result.score.value() >= args.min_score)
+        .collect::<Vec<_>>();
+
+    if let Some(path) = &args.emit_map {
+        let name = match &rom.mapped {
+            Some(mapped) => mapped.metadata.name.clone(),
+            None => rom_path
+                .file_stem()
+                .map_or_else(String::new, |stem| stem.to_string_lossy().into_owned()),
+        };
+
+        fs::write(path, thanatos::draft_map(&results, &name, rom.rom.crc()))
+            .with_context(|| "Failed to write draft ROM map")?;
+        log::info!("Wrote draft ROM map to {}", path.display());
+    }
+
+    for (kind, count) in found {
+        log::info!("Found {} potential {} streams", count, kind);
+    }
+
+    Ok(())
+}
+
+/// Write out the data of a scan result and whatever shows it best, returning its entry of
+/// `scan.json`.
+fn export_scan_result(
+    rom: &Rom,
+    result: &thanatos::ScanResult,
+    out_dir: &Path,
+    sheet: &thanatos::TileSheet,
+) -> anyhow::Result<String> {
+    let kind = result
+        .kind
+        .map_or_else(|| "unknown".to_string(), |kind| kind.to_string());
+
+    log::info!(
+        "Found potential {} of {} bytes at {:#07x}-{:#07x} ({}), score {:.2}{}",
+        kind,
+        result.data.len(),
+        result.offset,
+        result.end,
+        rom.address(result.offset),
+        result.score.value(),
+        match result.aliases.len() {
+            0 => String::new(),
+            1 => ", 1 alias".to_string(),
+            n => format!(", {} aliases", n),
+        }
+    );
+
+    let name = format!("{}_{:#07x}-{:#07x}", kind, result.offset, result.end);
+    fs::write(out_dir.join(format!("{}.bin", name)), &result.data)?;
+
+    let sheet_path = match result.kind {
+        Some(RegionKind::Palette) => {
+            let path = PathBuf::from(format!("{}.png", name));
+            export_scanned_palettes(&out_dir.join(&path), &result.data)?;
+            Some(path)
+        }
+        Some(RegionKind::TileSet) => {
+            let path = PathBuf::from(format!("{}.png", name));
+            let tiles = thanatos::PartialTileSet::try_from_slice(&result.data)?;
+            sheet
+                .render(tiles.tiles(), &thanatos::BW_PALETTE)
+                .save(out_dir.join(&path))?;
+            Some(path)
+        }
+        Some(RegionKind::TileMap) => {
+            let path = PathBuf::from(format!("{}.txt", name));
+            export_scanned_tilemap(&out_dir.join(&path), &result.data)?;
+            Some(path)
+        }
+        None => None,
+    };
+
+    let entry = ScanEntry {
+        offset: result.offset,
+        address: rom.address(result.offset).to_string(),
+        end: result.end,
+        length: result.data.len(),
+        kind: result.kind,
+        score: result.score.value(),
+        signals: &result.score,
+        sheet: sheet_path,
+        aliases: &result.aliases,
+    };
+
+    Ok(serde_json::to_string(&entry)?)
+}
+
+/// every palette as a row of its colors
+fn export_scanned_palettes(path: &Path, data: &[u8]) -> anyhow::Result<()> {
+    use image::{GenericImage, RgbImage};
+    use thanatos::Palette;
+
+    let rows = (data.len() / 32) as u32;
+    let mut img = RgbImage::new(16, rows);
+    for (row, chunk) in data.chunks_exact(32).enumerate() {
+        img.copy_from(&Palette::from_slice(chunk).to_image(), 0, row as u32)?;
+    }
+
+    const SCALE: u32 = 16;
+    let img = image::imageops::resize(
+        &img,
+        16 * SCALE,
+        rows * SCALE,
+        image::imageops::FilterType::Nearest,
+    );
+    img.save(path)?;
+
+    Ok(())
+}
+
+/// the entries as text, 32 to a row like a background layer
+fn export_scanned_tilemap(path: &Path, data: &[u8]) -> anyhow::Result<()> {
+    use std::fmt::Write;
+    use thanatos::TileMap;
+
+    let tile_map = TileMap::try_from_slice(data)?;
+
+    let mut text = String::new();
+    for i in 0..tile_map.len() {
+        let entry = &tile_map[i];
+        let settings = entry.tile_settings();
+        write!(
+            text,
+            "{:03x}:{}{}{}",
+            entry.tile_index(),
+            (entry.as_u16() >> 10) & 0x7,
+            if settings.x_flip { 'x' } else { '-' },
+            if settings.y_flip { 'y' } else { '-' },
+        )?;
+        text.push(if i % 32 == 31 { '\n' } else { ' ' });
+    }
+    fs::write(path, text)?;
+
+    Ok(())
+}
+
+fn info(rom: LoadedRom) {
+    let data = rom.rom.data();
+    let crc = rom.rom.crc();
+
+    if rom.rom.had_copier_header() {
+        log::info!("Skipped a 512 byte copier header");
+    }
+    log::info!("Size: {} KiB, CRC32: {:#010x}", data.len() / 1024, crc);
+    if rom.rom.base_crc() != crc {
+        log::info!("CRC32 before patching: {:#010x}", rom.rom.base_crc());
+    }
+    match rom
+        .map
+        .as_ref()
+        .and_then(|map| map.get_compatible_metadata(&rom.rom))
+    {
+        Some(metadata) => log::info!("Inbuilt ROM map: {}", metadata.name),
+        None => log::info!("Inbuilt ROM map: none"),
+    }
+
+    let Some(header) = rom.rom.header() else {
+        log::warn!("No internal header found, this may not be a SNES ROM or a bad dump");
+        return;
+    };
+
+    log::info!("Title: {}", header.title);
+    log::info!(
+        "Mapping: {}, {}",
+        header.map_mode,
+        if header.fast_rom {
+            "FastROM"
+        } else {
+            "SlowROM"
+        }
+    );
+    log::info!("Chipset: {:#04x}", header.chipset);
+    log::info!("ROM size: {} KiB", header.rom_size / 1024);
+    match header.ram_size {
+        0 => log::info!("RAM size: none"),
+        size => log::info!("RAM size: {} KiB", size / 1024),
+    }
+    log::info!(
+        "Region: {} ({:#04x})",
+        header.region_name().unwrap_or("unknown"),
+        header.region
+    );
+    log::info!("Version: 1.{}", header.version);
+
+    let checksum = rom.rom.checksum();
+    log::info!(
+        "Checksum: {:#06x}, complement {:#06x}, calculated {:#06x}",
+        header.checksum,
+        header.complement,
+        checksum
+    );
+    if !header.complement_matches() {
+        log::warn!("Checksum and complement don't add up, the header may be damaged");
+    }
+    if header.checksum != checksum {
+        log::warn!("Internal checksum doesn't match the data, this may be a bad dump");
+    }
+}
 
-    let progress = ProgressBar::new(rom.data().len() as u64);
+#[derive(Args, Debug, Clone)]
+struct InsertArgs {
+    /// What the data is
+    #[arg(short, long)]
+    kind: InsertKind,
 
-    let found = AtomicUsize::new(0);
-    let rom_len = rom.data().len();
-    (0..rom_len).into_par_iter().for_each(|offset| {
-        progress.inc(1);
+    /// Offset of the compressed region to replace, e.g. 0x8ddbb
+    #[arg(short, long, value_parser = parse_offset)]
+    region: usize,
 
-        let (tiles, end_position) = {
-            let result = Decompressor::new(&rom.data(), offset).decompress();
+    /// The decompressed data, like the .bin files written by scan
+    data: PathBuf,
+
+    /// Where to write the patches, the extension is replaced
+    #[arg(short, long)]
+    out: PathBuf,
+
+    /// Also write the modified ROM here
+    #[arg(long)]
+    save_rom: Option<PathBuf>,
+
+    /// Only use runs of at least this many unused bytes for data that has to be moved
+    #[arg(long, default_value_t = 0x100)]
+    min_free: usize,
+
+    /// Offset of a 24 bit pointer to the region, changed if the data has to be moved
+    #[arg(long = "pointer", value_parser = parse_offset)]
+    pointers: Vec<usize>,
+
+    /// Offset of a 16 bit pointer to the region whose bank is set elsewhere, like the operand of
+    /// an `LDY #$DDBB`. The data is only moved within its bank then.
+    #[arg(long = "short-pointer", value_parser = parse_offset)]
+    short_pointers: Vec<usize>,
+}
+
+#[derive(Debug, Clone, clap::ValueEnum)]
+enum InsertKind {
+    Palette,
+    Tileset,
+    Tilemap,
+}
+
+fn insert(rom: LoadedRom, args: &InsertArgs) -> anyhow::Result<()> {
+    use thanatos::{FreeSpace, PaletteData, PartialTileSet, Pointer, RomError, TileMap};
+
+    let original = rom.rom.clone();
+    let mut rom = rom.rom;
+    let data =
+        fs::read(&args.data).with_context(|| format!("Failed to read {}", args.data.display()))?;
+
+    // going through the type makes sure the game can read the data
+    let compressed = match args.kind {
+        InsertKind::Palette => PaletteData::try_from_slice(&data)?.compress(),
+        InsertKind::Tileset => PartialTileSet::try_from_slice(&data)?.compress(),
+        InsertKind::Tilemap => TileMap::try_from_slice(&data)?.compress(),
+    };
+
+    let mut free = FreeSpace::find(rom.data(), args.min_free);
+    log::debug!(
+        "{} bytes free in {} ranges",
+        free.len(),
+        free.ranges().len()
+    );
 
-            let result = match result {
-                Ok(result) => result,
-                Err(_) => return,
-            };
+    let pointers = args
+        .pointers
+        .iter()
+        .map(|&offset| Pointer::Long(offset))
+        .chain(
+            args.short_pointers
+                .iter()
+                .map(|&offset| Pointer::Short(offset)),
+        )
+        .collect::<Vec<_>>();
 
-            if result.data.len() == 0 || result.data.len() % 32 != 0 {
-                return;
+    let insertion = match rom.insert(args.region, &compressed, &pointers, &mut free) {
+        Err(error @ RomError::NoPointers(_)) => {
+            for pointer in rom.pointers_to(args.region) {
+                let (flag, offset) = match pointer {
+                    Pointer::Long(offset) => ("--pointer", offset),
+                    Pointer::Short(offset) => ("--short-pointer", offset),
+                };
+                log::info!(
+                    "Possible pointer: {} {:#x} ({})",
+                    flag,
+                    offset,
+                    rom.address(offset)
+                );
             }
+            return Err(error.into());
+        }
+        result => result?,
+    };
+
+    if insertion.relocated {
+        log::info!(
+            "Moved {} bytes from {:#x} ({}) to {:#x} ({})",
+            compressed.len(),
+            args.region,
+            rom.address(args.region),
+            insertion.offset,
+            rom.address(insertion.offset)
+        );
+        for pointer in &insertion.pointers {
+            log::info!(
+                "Repointed {:#x} ({})",
+                pointer.offset(),
+                rom.address(pointer.offset())
+            );
+        }
+    } else {
+        log::info!(
+            "Wrote {} bytes at {:#x} ({})",
+            compressed.len(),
+            insertion.offset,
+            rom.address(insertion.offset)
+        );
+    }
+
+    match rom.fix_checksum() {
+        Some(checksum) => log::info!("Updated checksum to {:#06x}", checksum),
+        None => log::warn!("No internal header found, the checksum wasn't updated"),
+    }
+
+    write_patches(&original, &rom, &args.out)?;
+
+    if let Some(path) = &args.save_rom {
+        rom.save(path)
+            .with_context(|| format!("Failed to write {}", path.display()))?;
+        log::info!("Saved ROM to {}", path.display());
+    }
+
+    Ok(())
+}
+
+fn create_patches(rom: LoadedRom, modified: &Path, out: &Path) -> anyhow::Result<()> {
+    let modified = Rom::open(modified)?;
+    if rom.rom.had_copier_header() != modified.had_copier_header() {
+        log::info!("Only one of the ROMs has a copier header, comparing them without it");
+    }
+    if rom.rom.data() == modified.data() {
+        log::warn!("The ROMs are identical");
+    }
+
+    write_patches(&rom.rom, &modified, out)
+}
+
+/// Write the changes from `original` to `modified` next to `out` in every patch format.
+fn write_patches(original: &Rom, modified: &Rom, out: &Path) -> anyhow::Result<()> {
+    use thanatos::PatchFormat;
+
+    if original.had_copier_header() {
+        log::info!(
+            "The patches apply to the ROM without its copier header, use --ips-base stripped to open it with the IPS one"
+        );
+    }
+    if original.base_crc() != original.crc() {
+        log::info!("The patches apply on top of the ones the ROM was opened with");
+    }
+
+    for format in PatchFormat::ALL {
+        let path = out.with_extension(format.extension());
+        let patch = format.create(original.data(), modified.data())?;
+        fs::write(&path, &patch).with_context(|| format!("Failed to write {}", path.display()))?;
+        log::info!("Wrote {} ({} bytes)", path.display(), patch.len());
+    }
+
+    Ok(())
+}
 
-            let tiles = match PartialTileSet::try_from_slice(&result.data) {
-                Ok(tiles) => tiles,
-                Err(_) => return,
-            };
+fn verify_compression(rom: LoadedRom) -> anyhow::Result<()> {
+    use thanatos::{CompressionMode, Compressor, Decompressor};
 
-            (tiles, offset + result.bytes_read)
+    let map = rom
+        .map
+        .with_context(|| "Failed to load ROM map. Please provide a valid ROM map.")?;
+    let data = rom.rom.data();
+
+    let regions = map.regions();
+    let mut identical = 0;
+    for region in &regions {
+        let result = match Decompressor::new(data, region.offset).decompress() {
+            Ok(result) => result,
+            Err(e) => {
+                println!(
+                    "{:#07x} {:<8} {:<24} failed to decompress: {}",
+                    region.offset, region.kind, region.name, e
+                );
+                continue;
+            }
         };
 
-        let tile_amt = tiles.tiles().len();
-        if tile_amt < args.threshold || tile_amt >= 1024 || !tile_amt.is_power_of_two() {
-            return;
+        let original = &data[region.offset..region.offset + result.bytes_read];
+        let encoded = Compressor::with_mode(&result.data, CompressionMode::Greedy).compress();
+
+        let status = if encoded == original {
+            identical += 1;
+            "identical".to_string()
+        } else {
+            let first_difference = original
+                .iter()
+                .zip(&encoded)
+                .position(|(a, b)| a != b)
+                .unwrap_or(original.len().min(encoded.len()));
+            format!("first difference at byte {:#x}", first_difference)
+        };
+
+        println!(
+            "{:#07x} {:<8} {:<24} {:>6} -> {:>6} bytes, {}",
+            region.offset,
+            region.kind,
+            region.name,
+            original.len(),
+            encoded.len(),
+            status
+        );
+    }
+
+    log::info!(
+        "{}/{} regions re-encoded byte-identical",
+        identical,
+        regions.len()
+    );
+
+    Ok(())
+}
+
+#[derive(Debug, Clone, clap::ValueEnum)]
+enum StatsFormat {
+    /// One line per region
+    Table,
+
+    /// A JSON array with one object per region
+    Json,
+}
+
+fn stats(rom: LoadedRom, format: StatsFormat) -> anyhow::Result<()> {
+    use thanatos::OperationKind;
+
+    let map = rom
+        .map
+        .with_context(|| "Failed to load ROM map. Please provide a valid ROM map.")?;
+    let regions = map.stats(&rom.rom)?;
+
+    match format {
+        StatsFormat::Json => {
+            println!("{}", serde_json::to_string_pretty(&regions)?);
         }
+        StatsFormat::Table => {
+            // every stream ends with exactly one exit, it's not worth a column
+            let kinds = &OperationKind::ALL[..OperationKind::ALL.len() - 1];
 
-        progress.println(format!(
-            "Found potential tile set with {} tiles at {:#07x}-{:#07x}",
-            tiles.tiles().len(),
-            offset,
-            end_position
-        ));
-
-        found.fetch_add(1, Ordering::SeqCst);
-
-        let folder_name = &out_dir.join(format!("tiles_{:#07x}-{:#07x}", offset, end_position));
-        fs::create_dir(folder_name).expect("Failed to create directory");
-        tiles.tiles().par_iter().enumerate().for_each(|(i, tile)| {
-            let img = tile.with_palette(&thanatos::BW_PALETTE, Default::default());
-
-            const SCALE: u32 = 10;
-            let img = image::imageops::resize(
-                &img,
-                8 * SCALE,
-                8 * SCALE,
-                image::imageops::FilterType::Nearest,
+            print!(
+                "{:<8} {:<8} {:<24} {:>6} {:>6} {:>6}",
+                "offset", "kind", "name", "packed", "size", "ratio"
             );
+            for kind in kinds {
+                print!(" {:>10}", kind.to_string());
+            }
+            println!(" {:>8} {:>8} {:>6}", "max read", "max ref", "nested");
 
-            img.save(folder_name.join(format!("tile_{:04}.png", i)))
-                .expect("Failed to save tile image");
-        });
-    });
+            for region in &regions {
+                let stats = &region.stats;
+                print!(
+                    "{:#08x} {:<8} {:<24} {:>6} {:>6} {:>5.1}%",
+                    region.region.offset,
+                    region.region.kind,
+                    region.region.name,
+                    stats.compressed_size,
+                    stats.decompressed_size,
+                    region.ratio * 100.0
+                );
+                for &kind in kinds {
+                    print!(" {:>10}", stats.count(kind));
+                }
+                println!(
+                    " {:>8} {:>8} {:>6}",
+                    stats.longest_backread, stats.longest_backref, stats.nested_backreads
+                );
+            }
 
-    progress.finish_and_clear();
+            let compressed = regions
+                .iter()
+                .map(|region| region.stats.compressed_size)
+                .sum::<usize>();
+            let decompressed = regions
+                .iter()
+                .map(|region| region.stats.decompressed_size)
+                .sum::<usize>();
+            log::info!(
+                "{} regions, {} bytes compressed to {} bytes",
+                regions.len(),
+                decompressed,
+                compressed
+            );
+        }
+    }
+
+    Ok(())
+}
+
+fn explain(rom: LoadedRom, offset: usize, permissive: bool) -> anyhow::Result<()> {
+    use thanatos::{DecompressOptions, Decompressor, TraceEvent};
+
+    let options = if permissive {
+        DecompressOptions::permissive()
+    } else {
+        DecompressOptions::default()
+    };
+    let (trace, result) =
+        Decompressor::with_options(rom.rom.data(), offset, options).decompress_traced();
+
+    for event in &trace {
+        match event {
+            TraceEvent::Operation(operation) => {
+                let raw = operation
+                    .raw
+                    .iter()
+                    .map(|value| format!("{:02x}", value))
+                    .collect::<Vec<_>>()
+                    .join(" ");
+                let payload = if operation.payload.is_empty() {
+                    String::new()
+                } else {
+                    format!(" + {} bytes", operation.payload.len())
+                };
+
+                println!(
+                    "{:#08x}  {:<8}  {:<64} {:#06x}..{:#06x}",
+                    operation.offset,
+                    raw,
+                    format!("{:?}{}", operation.operation, payload),
+                    operation.output.start,
+                    operation.output.end
+                );
+            }
+            TraceEvent::BackrefStart { to, count, .. } => {
+                println!(
+                    "          backref: re-reading {} bytes at {:#08x}",
+                    count, to
+                );
+            }
+            TraceEvent::BackrefReturn { from, to } => {
+                println!(
+                    "          backref done at {:#08x}, returning to {:#08x}",
+                    from, to
+                );
+            }
+        }
+    }
+
+    let result = result.with_context(|| format!("Failed to decompress data at {:#07x}", offset))?;
     log::info!(
-        "Done! Found {} potential tile sets",
-        found.load(Ordering::SeqCst)
+        "Decompressed {} bytes from {} bytes of compressed data",
+        result.data.len(),
+        result.bytes_read
     );
 
     Ok(())
 }
+
+#[cfg(feature = "decompress-old")]
+fn differential(rom: LoadedRom) -> anyhow::Result<()> {
+    use indicatif::ProgressBar;
+    use std::collections::BTreeMap;
+    use thanatos::Mismatch;
+
+    log::info!("Comparing decompressors on the entire ROM...");
+
+    let data = rom.rom.data();
+    let progress = ProgressBar::new(data.len() as u64);
+    let disagreements = thanatos::compare_all(data, |_| progress.inc(1));
+    progress.finish_and_clear();
+
+    // the new decompressor is stricter, so it failing on its own is expected and only counted
+    let mut new_failed = BTreeMap::new();
+    let mut listed = 0;
+    for disagreement in &disagreements {
+        if let Mismatch::NewFailed(error) = &disagreement.mismatch {
+            *new_failed.entry(error.summary()).or_insert(0) += 1;
+            continue;
+        }
+
+        let operation = disagreement
+            .operation
+            .as_ref()
+            .map(|operation| format!("{:?} at {:#08x}", operation.operation, operation.offset))
+            .unwrap_or_else(|| "no operation".to_string());
+
+        println!(
+            "{:#08x} output byte {:#06x}: {:?}, {}",
+            disagreement.offset, disagreement.position, disagreement.mismatch, operation
+        );
+        listed += 1;
+    }
+
+    for (error, count) in new_failed {
+        log::info!(
+            "only the new decompressor failed with '{}' {} times",
+            error,
+            count
+        );
+    }
+    log::info!("{} offsets where the decompressors disagree", listed);
+
+    Ok(())
+}
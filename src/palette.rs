@@ -1,35 +1,58 @@
 use crate::{Compressable, DecompressError};
-use image::Rgb;
+use alloc::{format, vec::Vec};
+#[cfg(feature = "std")]
 use serde::Deserialize;
 
 pub const BW_PALETTE: Palette = Palette([
-    Rgb([0, 0, 0]),
-    Rgb([255, 255, 255]),
-    Rgb([170, 170, 170]),
-    Rgb([85, 85, 85]),
-    Rgb([0, 0, 0]),
-    Rgb([255, 255, 255]),
-    Rgb([170, 170, 170]),
-    Rgb([85, 85, 85]),
-    Rgb([0, 0, 0]),
-    Rgb([255, 255, 255]),
-    Rgb([170, 170, 170]),
-    Rgb([85, 85, 85]),
-    Rgb([0, 0, 0]),
-    Rgb([255, 255, 255]),
-    Rgb([170, 170, 170]),
-    Rgb([85, 85, 85]),
+    Color([0, 0, 0]),
+    Color([255, 255, 255]),
+    Color([170, 170, 170]),
+    Color([85, 85, 85]),
+    Color([0, 0, 0]),
+    Color([255, 255, 255]),
+    Color([170, 170, 170]),
+    Color([85, 85, 85]),
+    Color([0, 0, 0]),
+    Color([255, 255, 255]),
+    Color([170, 170, 170]),
+    Color([85, 85, 85]),
+    Color([0, 0, 0]),
+    Color([255, 255, 255]),
+    Color([170, 170, 170]),
+    Color([85, 85, 85]),
 ]);
 
+/// A color with 8 bits per channel, converted from the 15 bit SNES format.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Color(pub [u8; 3]);
+
+impl Color {
+    /// The color in the 15 bit BGR format of the SNES, dropping the lowest 3 bits of each
+    /// channel.
+    pub fn to_bgr555(self) -> u16 {
+        let [r, g, b] = self.0.map(|channel| (channel >> 3) as u16);
+        r | g << 5 | b << 10
+    }
+}
+
+#[cfg(feature = "std")]
+impl From<Color> for image::Rgb<u8> {
+    fn from(color: Color) -> Self {
+        image::Rgb(color.0)
+    }
+}
+
 #[derive(Debug, Clone, Copy)]
-pub struct Palette([Rgb<u8>; 16]);
+pub struct Palette([Color; 16]);
 #[derive(Debug, Clone)]
 pub struct PaletteCollection([Palette; 16]);
 
-#[derive(Debug, Clone, Copy, Deserialize)]
+#[derive(Debug, Clone, Copy)]
+#[cfg_attr(feature = "std", derive(Deserialize))]
 pub struct ColorIndex(usize);
 
-#[derive(Debug, Clone, Copy, Deserialize)]
+#[derive(Debug, Clone, Copy)]
+#[cfg_attr(feature = "std", derive(Deserialize))]
 pub struct PaletteIndex(usize);
 
 impl ColorIndex {
@@ -42,6 +65,10 @@ impl ColorIndex {
     pub const fn is_transparent(&self) -> bool {
         self.0 == 0
     }
+
+    pub const fn index(&self) -> usize {
+        self.0
+    }
 }
 
 impl PaletteIndex {
@@ -52,7 +79,7 @@ impl PaletteIndex {
     }
 }
 
-impl std::ops::Index<PaletteIndex> for PaletteCollection {
+impl core::ops::Index<PaletteIndex> for PaletteCollection {
     type Output = Palette;
 
     fn index(&self, index: PaletteIndex) -> &Self::Output {
@@ -60,9 +87,15 @@ impl std::ops::Index<PaletteIndex> for PaletteCollection {
     }
 }
 
+impl core::ops::IndexMut<PaletteIndex> for PaletteCollection {
+    fn index_mut(&mut self, index: PaletteIndex) -> &mut Self::Output {
+        &mut self.0[index.0]
+    }
+}
+
 impl PaletteCollection {
     pub fn add_palette_data(&mut self, offset: usize, data: &[u8]) {
-        if data.len() % 32 != 0 {
+        if !data.len().is_multiple_of(32) {
             panic!("Palette data must be a multiple of 32 bytes");
         }
 
@@ -73,7 +106,7 @@ impl PaletteCollection {
         }
 
         data.chunks_exact(32)
-            .map(|palette_data| Palette::from_slice(palette_data))
+            .map(Palette::from_slice)
             .enumerate()
             .for_each(|(i, palette)| {
                 self.0[offset + i] = palette;
@@ -85,16 +118,58 @@ impl Compressable for PaletteCollection {
     /// Convert a slice of bytes into a collection of SNES palettes.
     fn try_from_slice(data: &[u8]) -> Result<Self, DecompressError> {
         if data.len() != 512 {
-            return Err(DecompressError::InvalidLayout(format!(
+            return Err(DecompressError::invalid_layout(format!(
                 "Palette data must be 512 bytes long, was {}",
                 data.len()
             )));
         }
 
-        let mut collection = PaletteCollection([Palette([Rgb([0, 0, 0]); 16]); 16]);
+        let mut collection = PaletteCollection([Palette([Color([0, 0, 0]); 16]); 16]);
         collection.add_palette_data(0, data);
         Ok(collection)
     }
+
+    fn to_bytes(&self) -> Vec<u8> {
+        self.0.iter().flat_map(Palette::to_bytes).collect()
+    }
+}
+
+/// Palettes as stored in the ROM, between 1 and 16 of them. Unlike [`PaletteCollection`] the
+/// colors are kept as they are, including the unused top bit, so the data can be written back
+/// unchanged. Maps place these into a collection with a `start` offset.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PaletteData(Vec<u8>);
+
+impl PaletteData {
+    /// How many palettes the data holds.
+    pub fn len(&self) -> usize {
+        self.0.len() / 32
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+
+    pub fn palettes(&self) -> impl Iterator<Item = Palette> + '_ {
+        self.0.chunks_exact(32).map(Palette::from_slice)
+    }
+}
+
+impl Compressable for PaletteData {
+    fn try_from_slice(data: &[u8]) -> Result<Self, DecompressError> {
+        if data.is_empty() || data.len() > 512 || !data.len().is_multiple_of(32) {
+            return Err(DecompressError::invalid_layout(format!(
+                "Palette data must be between 1 and 16 palettes of 32 bytes, was {} bytes",
+                data.len()
+            )));
+        }
+
+        Ok(PaletteData(data.to_vec()))
+    }
+
+    fn to_bytes(&self) -> Vec<u8> {
+        self.0.clone()
+    }
 }
 
 impl Palette {
@@ -102,23 +177,46 @@ impl Palette {
     pub fn from_slice(data: &[u8]) -> Self {
         assert!(data.len() == 32, "Palette data must be 32 bytes long");
 
-        let mut palette = [Rgb([0, 0, 0]); 16];
+        let mut palette = [Color([0, 0, 0]); 16];
         for (i, color) in data.chunks_exact(2).enumerate() {
             let val_rgb15 = u16::from_le_bytes([color[0], color[1]]);
-            let r = ((val_rgb15 >> 0) & 0x1F) << 3;
+            let r = (val_rgb15 & 0x1F) << 3;
             let g = ((val_rgb15 >> 5) & 0x1F) << 3;
             let b = ((val_rgb15 >> 10) & 0x1F) << 3;
 
-            palette[i] = Rgb([r as u8, g as u8, b as u8]);
+            palette[i] = Color([r as u8, g as u8, b as u8]);
         }
         Palette(palette)
     }
+
+    /// The palette in the format [`Palette::from_slice`] reads.
+    pub fn to_bytes(&self) -> [u8; 32] {
+        let mut data = [0; 32];
+        for (color, bytes) in self.0.iter().zip(data.chunks_exact_mut(2)) {
+            bytes.copy_from_slice(&color.to_bgr555().to_le_bytes());
+        }
+        data
+    }
 }
 
-impl std::ops::Index<ColorIndex> for Palette {
-    type Output = Rgb<u8>;
+#[cfg(feature = "std")]
+impl Palette {
+    /// The colors of the palette as a 16x1 image.
+    pub fn to_image(&self) -> image::RgbImage {
+        image::RgbImage::from_fn(16, 1, |x, _| self.0[x as usize].into())
+    }
+}
+
+impl core::ops::Index<ColorIndex> for Palette {
+    type Output = Color;
 
     fn index(&self, index: ColorIndex) -> &Self::Output {
         &self.0[index.0]
     }
 }
+
+impl core::ops::IndexMut<ColorIndex> for Palette {
+    fn index_mut(&mut self, index: ColorIndex) -> &mut Self::Output {
+        &mut self.0[index.0]
+    }
+}
@@ -0,0 +1,102 @@
+//! Patches describing the changes made to a ROM, since the ROM itself can't be passed around.
+
+use thiserror::Error;
+
+mod bps;
+mod ips;
+
+#[derive(Error, Debug, PartialEq, Eq)]
+pub enum PatchError {
+    #[error("IPS patches can't address data past 16MiB, the modified ROM is {0} bytes")]
+    TooLarge(usize),
+    #[error("Not an IPS or BPS patch")]
+    UnknownFormat,
+    #[error("Patch ends in the middle of a record")]
+    Truncated,
+    #[error("Patch reads outside of the data it is applied to")]
+    OutOfBounds,
+    #[error("Patch is damaged, its checksum doesn't match")]
+    Corrupt,
+    #[error("Patch was made for a ROM with CRC {expected:08x}, but this one has {actual:08x}")]
+    SourceMismatch { expected: u32, actual: u32 },
+    #[error("Patched ROM should have CRC {expected:08x}, but has {actual:08x}")]
+    TargetMismatch { expected: u32, actual: u32 },
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PatchFormat {
+    Ips,
+    Bps,
+}
+
+impl PatchFormat {
+    pub const ALL: [PatchFormat; 2] = [PatchFormat::Ips, PatchFormat::Bps];
+
+    /// The format of `patch`, going by its magic bytes.
+    pub fn detect(patch: &[u8]) -> Option<Self> {
+        if patch.starts_with(ips::MAGIC) {
+            Some(PatchFormat::Ips)
+        } else if patch.starts_with(bps::MAGIC) {
+            Some(PatchFormat::Bps)
+        } else {
+            None
+        }
+    }
+
+    /// File extension used for patches of this format, without the dot.
+    pub fn extension(self) -> &'static str {
+        match self {
+            PatchFormat::Ips => "ips",
+            PatchFormat::Bps => "bps",
+        }
+    }
+
+    /// A patch that turns `original` into `modified`.
+    pub fn create(self, original: &[u8], modified: &[u8]) -> Result<Vec<u8>, PatchError> {
+        match self {
+            PatchFormat::Ips => ips::create(original, modified),
+            PatchFormat::Bps => Ok(bps::create(original, modified)),
+        }
+    }
+
+    /// Apply `patch` to `source`. BPS patches are checked against the checksums they carry.
+    pub fn apply(self, source: &[u8], patch: &[u8]) -> Result<Vec<u8>, PatchError> {
+        match self {
+            PatchFormat::Ips => ips::apply(source, patch),
+            PatchFormat::Bps => bps::apply(source, patch),
+        }
+    }
+}
+
+/// Reads the fields of a patch, failing once it runs out.
+struct Reader<'patch> {
+    patch: &'patch [u8],
+    position: usize,
+}
+
+impl<'patch> Reader<'patch> {
+    fn new(patch: &'patch [u8], position: usize) -> Self {
+        Self { patch, position }
+    }
+
+    fn bytes(&mut self, len: usize) -> Result<&'patch [u8], PatchError> {
+        let end = self
+            .position
+            .checked_add(len)
+            .ok_or(PatchError::Truncated)?;
+        let bytes = self
+            .patch
+            .get(self.position..end)
+            .ok_or(PatchError::Truncated)?;
+        self.position = end;
+        Ok(bytes)
+    }
+
+    fn byte(&mut self) -> Result<u8, PatchError> {
+        Ok(self.bytes(1)?[0])
+    }
+
+    fn remaining(&self) -> usize {
+        self.patch.len() - self.position
+    }
+}
@@ -0,0 +1,217 @@
+//! The BPS format: actions building the modified data out of the original and itself, with
+//! checksums of both so patchers can tell whether they got the right ROM.
+
+use super::{PatchError, Reader};
+
+pub(super) const MAGIC: &[u8] = b"BPS1";
+/// Source, target and patch CRC32.
+const FOOTER_SIZE: usize = 12;
+
+const SOURCE_READ: usize = 0;
+const TARGET_READ: usize = 1;
+const SOURCE_COPY: usize = 2;
+const TARGET_COPY: usize = 3;
+
+/// Runs of the same byte at least this long are copied from the byte before.
+const MIN_RUN: usize = 4;
+
+/// Most space reserved for the target before applying, since a patch can claim any size.
+const MAX_RESERVED: usize = 0x1000000;
+
+/// A patch that turns `source` into `target`, without metadata.
+pub fn create(source: &[u8], target: &[u8]) -> Vec<u8> {
+    let mut patch = MAGIC.to_vec();
+    write_number(&mut patch, source.len());
+    write_number(&mut patch, target.len());
+    write_number(&mut patch, 0);
+
+    let unchanged = |i: usize| source.get(i) == Some(&target[i]);
+
+    let mut target_relative = 0;
+    let mut output = 0;
+    while output < target.len() {
+        let same = (output..target.len()).take_while(|&i| unchanged(i)).count();
+        if same > 0 {
+            write_number(&mut patch, (same - 1) << 2 | SOURCE_READ);
+            output += same;
+            continue;
+        }
+
+        let end = (output..target.len())
+            .find(|&i| unchanged(i))
+            .unwrap_or(target.len());
+
+        let mut literal_start = output;
+        let mut i = output;
+        while i < end {
+            let run = target[i..end]
+                .iter()
+                .take_while(|&&byte| byte == target[i])
+                .count();
+            if run < MIN_RUN {
+                i += run;
+                continue;
+            }
+
+            // the first byte of the run is read, the rest copied from the byte before
+            write_target_read(&mut patch, &target[literal_start..=i]);
+            write_number(&mut patch, (run - 2) << 2 | TARGET_COPY);
+            write_signed(&mut patch, i as isize - target_relative as isize);
+            target_relative = i + run - 1;
+
+            i += run;
+            literal_start = i;
+        }
+        write_target_read(&mut patch, &target[literal_start..end]);
+
+        output = end;
+    }
+
+    patch.extend_from_slice(&crc32fast::hash(source).to_le_bytes());
+    patch.extend_from_slice(&crc32fast::hash(target).to_le_bytes());
+    let checksum = crc32fast::hash(&patch);
+    patch.extend_from_slice(&checksum.to_le_bytes());
+
+    patch
+}
+
+/// Apply `patch` to `source`, checking that both are the ones the patch was made for.
+pub fn apply(source: &[u8], patch: &[u8]) -> Result<Vec<u8>, PatchError> {
+    if !patch.starts_with(MAGIC) {
+        return Err(PatchError::UnknownFormat);
+    }
+    if patch.len() < MAGIC.len() + FOOTER_SIZE {
+        return Err(PatchError::Truncated);
+    }
+
+    let (body, footer) = patch.split_at(patch.len() - FOOTER_SIZE);
+    let crc = |bytes: &[u8]| u32::from_le_bytes(bytes.try_into().unwrap());
+    if crc32fast::hash(&patch[..patch.len() - 4]) != crc(&footer[8..]) {
+        return Err(PatchError::Corrupt);
+    }
+
+    let expected = crc(&footer[..4]);
+    let actual = crc32fast::hash(source);
+    if expected != actual {
+        return Err(PatchError::SourceMismatch { expected, actual });
+    }
+
+    let mut reader = Reader::new(body, MAGIC.len());
+    let source_size = read_number(&mut reader)?;
+    let target_size = read_number(&mut reader)?;
+    let metadata_size = read_number(&mut reader)?;
+    reader.bytes(metadata_size)?;
+    if source_size != source.len() {
+        return Err(PatchError::Corrupt);
+    }
+
+    let mut target = Vec::with_capacity(target_size.min(MAX_RESERVED));
+    let mut source_relative = 0;
+    let mut target_relative = 0;
+    while reader.remaining() > 0 {
+        let action = read_number(&mut reader)?;
+        let len = (action >> 2) + 1;
+        if len > target_size - target.len() {
+            return Err(PatchError::OutOfBounds);
+        }
+
+        match action & 3 {
+            SOURCE_READ => {
+                let start = target.len();
+                let bytes = start
+                    .checked_add(len)
+                    .and_then(|end| source.get(start..end))
+                    .ok_or(PatchError::OutOfBounds)?;
+                target.extend_from_slice(bytes);
+            }
+            TARGET_READ => target.extend_from_slice(reader.bytes(len)?),
+            SOURCE_COPY => {
+                source_relative = relative(source_relative, &mut reader)?;
+                let bytes = source_relative
+                    .checked_add(len)
+                    .and_then(|end| source.get(source_relative..end))
+                    .ok_or(PatchError::OutOfBounds)?;
+                target.extend_from_slice(bytes);
+                source_relative += len;
+            }
+            _ => {
+                target_relative = relative(target_relative, &mut reader)?;
+                // may overlap what is being written, which repeats the data
+                for _ in 0..len {
+                    let byte = *target.get(target_relative).ok_or(PatchError::OutOfBounds)?;
+                    target.push(byte);
+                    target_relative += 1;
+                }
+            }
+        }
+    }
+    if target.len() != target_size {
+        return Err(PatchError::Corrupt);
+    }
+
+    let expected = crc(&footer[4..8]);
+    let actual = crc32fast::hash(&target);
+    if expected != actual {
+        return Err(PatchError::TargetMismatch { expected, actual });
+    }
+
+    Ok(target)
+}
+
+fn read_number(reader: &mut Reader) -> Result<usize, PatchError> {
+    let mut number: usize = 0;
+    let mut shift: usize = 1;
+    loop {
+        let byte = reader.byte()?;
+        number = (byte as usize & 0x7f)
+            .checked_mul(shift)
+            .and_then(|bits| number.checked_add(bits))
+            .ok_or(PatchError::Corrupt)?;
+        if byte & 0x80 != 0 {
+            return Ok(number);
+        }
+
+        shift = shift.checked_mul(0x80).ok_or(PatchError::Corrupt)?;
+        number = number.checked_add(shift).ok_or(PatchError::Corrupt)?;
+    }
+}
+
+/// Move `offset` by the signed number that follows.
+fn relative(offset: usize, reader: &mut Reader) -> Result<usize, PatchError> {
+    let number = read_number(reader)?;
+    let delta = (number >> 1) as isize;
+    let delta = if number & 1 != 0 { -delta } else { delta };
+    offset
+        .checked_add_signed(delta)
+        .ok_or(PatchError::OutOfBounds)
+}
+
+fn write_target_read(patch: &mut Vec<u8>, data: &[u8]) {
+    if data.is_empty() {
+        return;
+    }
+
+    write_number(patch, (data.len() - 1) << 2 | TARGET_READ);
+    patch.extend_from_slice(data);
+}
+
+/// Numbers are stored 7 bits at a time, with the last byte marked by its high bit. Every byte
+/// that follows adds one to the rest, so each number only has a single encoding.
+fn write_number(patch: &mut Vec<u8>, mut number: usize) {
+    loop {
+        let bits = (number & 0x7f) as u8;
+        number >>= 7;
+        if number == 0 {
+            patch.push(0x80 | bits);
+            break;
+        }
+
+        patch.push(bits);
+        number -= 1;
+    }
+}
+
+/// Relative offsets are stored with their sign in the lowest bit.
+fn write_signed(patch: &mut Vec<u8>, number: isize) {
+    write_number(patch, number.unsigned_abs() << 1 | (number < 0) as usize);
+}
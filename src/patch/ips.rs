@@ -0,0 +1,148 @@
+//! The IPS format: a list of records overwriting the data at 24 bit offsets.
+
+use super::{PatchError, Reader};
+
+pub(super) const MAGIC: &[u8] = b"PATCH";
+const FOOTER: &[u8] = b"EOF";
+
+/// Largest size of a single record.
+const MAX_RECORD: usize = 0xffff;
+/// Offsets are 24 bit.
+const MAX_SIZE: usize = 0x1000000;
+/// Unchanged bytes between two changes that are cheaper to repeat than to start a new record for.
+const MAX_GAP: usize = 5;
+/// Runs of the same byte at least this long are written as RLE records.
+const MIN_RUN: usize = 9;
+/// Reads as "EOF", which ends the patch when a record starts there.
+const EOF_OFFSET: usize = 0x454f46;
+
+/// A patch that turns `original` into `modified`. If `modified` is shorter, the patch ends with
+/// the truncation extension most patchers understand.
+pub fn create(original: &[u8], modified: &[u8]) -> Result<Vec<u8>, PatchError> {
+    if modified.len() > MAX_SIZE {
+        return Err(PatchError::TooLarge(modified.len()));
+    }
+
+    let changed = |i: usize| original.get(i) != Some(&modified[i]);
+
+    let mut patch = MAGIC.to_vec();
+    let mut i = 0;
+    while i < modified.len() {
+        if !changed(i) {
+            i += 1;
+            continue;
+        }
+
+        let mut end = i + 1;
+        while end < modified.len() && end - i < MAX_RECORD {
+            if changed(end) {
+                end += 1;
+            } else if (end..(end + MAX_GAP + 1).min(modified.len())).any(changed) {
+                // the gap is worth including, but only up to the next change
+                end += 1;
+            } else {
+                break;
+            }
+        }
+
+        // start one byte earlier instead of at an offset that ends the patch
+        let start = if i == EOF_OFFSET { i - 1 } else { i };
+        let end = end.min(start + MAX_RECORD);
+        write_block(&mut patch, start, &modified[start..end]);
+        i = end;
+    }
+
+    patch.extend_from_slice(FOOTER);
+    if original.len() > modified.len() {
+        patch.extend_from_slice(&(modified.len() as u32).to_be_bytes()[1..]);
+    }
+
+    Ok(patch)
+}
+
+/// Write the records for `data` at `offset`, using RLE records for long runs.
+fn write_block(patch: &mut Vec<u8>, offset: usize, data: &[u8]) {
+    let mut literal_start = 0;
+    let mut i = 0;
+    while i < data.len() {
+        let mut run = data[i..]
+            .iter()
+            .take_while(|&&byte| byte == data[i])
+            .count();
+        // neither record may start at the offset that ends the patch
+        if run >= MIN_RUN && offset + i + run == EOF_OFFSET {
+            run -= 1;
+        }
+        if run < MIN_RUN || offset + i == EOF_OFFSET {
+            i += run;
+            continue;
+        }
+
+        write_literal(patch, offset + literal_start, &data[literal_start..i]);
+        patch.extend_from_slice(&offset_bytes(offset + i));
+        patch.extend_from_slice(&[0, 0]);
+        patch.extend_from_slice(&(run as u16).to_be_bytes());
+        patch.push(data[i]);
+
+        i += run;
+        literal_start = i;
+    }
+
+    write_literal(patch, offset + literal_start, &data[literal_start..]);
+}
+
+fn write_literal(patch: &mut Vec<u8>, offset: usize, data: &[u8]) {
+    if data.is_empty() {
+        return;
+    }
+
+    patch.extend_from_slice(&offset_bytes(offset));
+    patch.extend_from_slice(&(data.len() as u16).to_be_bytes());
+    patch.extend_from_slice(data);
+}
+
+fn offset_bytes(offset: usize) -> [u8; 3] {
+    let [_, high, mid, low] = (offset as u32).to_be_bytes();
+    [high, mid, low]
+}
+
+/// Apply `patch` to `source`, growing it if records write past its end.
+pub fn apply(source: &[u8], patch: &[u8]) -> Result<Vec<u8>, PatchError> {
+    if !patch.starts_with(MAGIC) {
+        return Err(PatchError::UnknownFormat);
+    }
+
+    let mut data = source.to_vec();
+    let mut reader = Reader::new(patch, MAGIC.len());
+    loop {
+        let offset = reader.bytes(3)?;
+        if offset == FOOTER {
+            break;
+        }
+        let offset = u32::from_be_bytes([0, offset[0], offset[1], offset[2]]) as usize;
+
+        let len = u16::from_be_bytes(reader.bytes(2)?.try_into().unwrap()) as usize;
+        let (len, record) = match len {
+            0 => {
+                let len = u16::from_be_bytes(reader.bytes(2)?.try_into().unwrap()) as usize;
+                (len, None)
+            }
+            len => (len, Some(reader.bytes(len)?)),
+        };
+
+        if data.len() < offset + len {
+            data.resize(offset + len, 0);
+        }
+        match record {
+            Some(record) => data[offset..offset + len].copy_from_slice(record),
+            None => data[offset..offset + len].fill(reader.byte()?),
+        }
+    }
+
+    if reader.remaining() == 3 {
+        let len = reader.bytes(3)?;
+        data.truncate(u32::from_be_bytes([0, len[0], len[1], len[2]]) as usize);
+    }
+
+    Ok(data)
+}
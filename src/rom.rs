@@ -1,19 +1,44 @@
-use std::{borrow::Cow, collections::HashMap, fs, path::Path, sync::Arc};
+use std::{
+    borrow::Cow,
+    collections::{hash_map::Entry, HashMap},
+    fs,
+    path::{Path, PathBuf},
+    sync::Arc,
+};
 use thiserror::Error;
 
 use crate::{
-    tile::PartialTileSet, Compressable, DecompressError, Decompressor, PaletteCollection, Sprite,
-    TileMap, TileSet,
+    tile::PartialTileSet, Compressable, DecompressError, Decompressor, PaletteCollection,
+    PatchError, PatchFormat, SnesAddress, Sprite, TileMap, TileSet,
 };
 
+mod header;
+pub use header::SnesHeader;
+mod insert;
+pub use insert::{FreeSpace, Insertion, Pointer, FILL_BYTE};
+mod mapper;
+pub use mapper::MapMode;
 mod map;
-pub use map::RomMap;
 use map::RomMetadata;
+pub use map::{MapRegion, MapTarget, RegionKind, RegionStats, RomMap};
 
 #[derive(Debug, Clone)]
 pub struct Rom<'rom> {
     data: Cow<'rom, [u8]>,
     crc: u32,
+    header: Option<SnesHeader>,
+    copier_header: bool,
+    /// CRC32 of the data before any patches were applied
+    base_crc: u32,
+    map_target: MapTarget,
+}
+
+/// Which image of a ROM with a copier header an IPS patch was made for. Unlike BPS patches they
+/// have no checksum of the source to tell.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum IpsBase {
+    Stripped,
+    Headered,
 }
 
 #[derive(Debug, Clone)]
@@ -40,10 +65,39 @@ pub struct MappedSprite {
 pub enum RomError {
     #[error("Failed to read ROM file")]
     Read(#[from] std::io::Error),
-    #[error("Failed to decompress data")]
-    Decompress(#[from] DecompressError),
+    #[error("Failed to apply patch {path}")]
+    Patch {
+        path: PathBuf,
+        #[source]
+        source: PatchError,
+    },
+    #[error("The ROM has a copier header, so it's unclear whether IPS patch {0} was made for it with or without it")]
+    AmbiguousIpsPatch(PathBuf),
+    #[error("Failed to decompress {kind} '{name}' at {offset:#x} ({address})")]
+    Decompress {
+        kind: RegionKind,
+        /// name of the map definition the data belongs to
+        name: String,
+        offset: usize,
+        /// where the region is read from, according to the mapping of the map
+        address: SnesAddress,
+        #[source]
+        source: DecompressError,
+    },
     #[error("Incompatible ROM map")]
     IncompatibleMap,
+    #[error("No compressed data at {offset:#x} to replace")]
+    InvalidRegion {
+        offset: usize,
+        #[source]
+        source: DecompressError,
+    },
+    #[error("Not enough free space for {0} bytes")]
+    NoFreeSpace(usize),
+    #[error("The data at {0:#x} has to be moved, but no pointers to it were given")]
+    NoPointers(usize),
+    #[error("{pointer:#x} doesn't point at {region:#x}")]
+    NotAPointer { pointer: usize, region: usize },
 
     #[error("Invalid palette definition for '{0}', first region cannot have a start offset")]
     InvalidPaletteDefinition(String),
@@ -54,27 +108,200 @@ pub enum RomError {
     UnknownTileset(String, String),
 }
 
+impl RomError {
+    fn decompress(kind: RegionKind, name: &str, map: &RomMap, source: DecompressError) -> Self {
+        let offset = source.context().region_start;
+        RomError::Decompress {
+            kind,
+            name: name.to_string(),
+            offset,
+            address: map.address(offset),
+            source,
+        }
+    }
+}
+
+/// Apply `patch` to the data with its copier header in front, keeping the header apart again.
+fn apply_headered(
+    format: PatchFormat,
+    header: &mut Vec<u8>,
+    data: &[u8],
+    patch: &[u8],
+) -> Result<Vec<u8>, PatchError> {
+    let mut image = format.apply(&[header.as_slice(), data].concat(), patch)?;
+    if image.len() < header::COPIER_HEADER_SIZE {
+        return Err(PatchError::OutOfBounds);
+    }
+
+    let data = image.split_off(header::COPIER_HEADER_SIZE);
+    *header = image;
+    Ok(data)
+}
+
+/// Read a ROM image, splitting off the copier header if it has one.
+fn read_image(path: &Path) -> Result<(Option<Vec<u8>>, Vec<u8>), RomError> {
+    let mut image = fs::read(path)?;
+    if !header::has_copier_header(&image) {
+        return Ok((None, image));
+    }
+
+    let data = image.split_off(header::COPIER_HEADER_SIZE);
+    Ok((Some(image), data))
+}
+
 impl<'rom> Rom<'rom> {
+    /// Read a ROM image, dropping the copier header if it has one.
     pub fn open<P: AsRef<Path>>(path: P) -> Result<Self, RomError> {
-        let rom = fs::read(path.as_ref())?;
-        let crc = crc32fast::hash(&rom);
-        let data = Cow::Owned(rom);
-        Ok(Self { data, crc })
+        let (copier_header, rom) = read_image(path.as_ref())?;
+        Ok(Self::from_data(Cow::Owned(rom), copier_header.is_some()))
+    }
+
+    /// Read a ROM image and apply IPS or BPS patches to it in order. Inbuilt ROM maps are picked
+    /// by the CRC of the unpatched image unless the [`MapTarget`] is changed.
+    ///
+    /// If the image has a copier header, BPS patches are applied to whichever image their
+    /// checksum matches and IPS patches to the one given by `ips_base`. IPS patches are refused
+    /// without it.
+    pub fn open_patched<P: AsRef<Path>, Q: AsRef<Path>>(
+        path: P,
+        patches: &[Q],
+        ips_base: Option<IpsBase>,
+    ) -> Result<Self, RomError> {
+        let (mut copier_header, data) = read_image(path.as_ref())?;
+        let base = Self::from_data(Cow::Owned(data), copier_header.is_some());
+        if patches.is_empty() {
+            return Ok(base);
+        }
+
+        let mut data = base.data.into_owned();
+        for path in patches {
+            let path = path.as_ref();
+            let patch = fs::read(path)?;
+            let error = |source| RomError::Patch {
+                path: path.to_path_buf(),
+                source,
+            };
+            let format = PatchFormat::detect(&patch).ok_or(error(PatchError::UnknownFormat))?;
+
+            data = match (copier_header.as_mut(), format) {
+                (None, _) => format.apply(&data, &patch),
+                (Some(header), PatchFormat::Ips) => match ips_base {
+                    None => return Err(RomError::AmbiguousIpsPatch(path.to_path_buf())),
+                    Some(IpsBase::Stripped) => format.apply(&data, &patch),
+                    Some(IpsBase::Headered) => apply_headered(format, header, &data, &patch),
+                },
+                (Some(header), PatchFormat::Bps) => match format.apply(&data, &patch) {
+                    Err(stripped_error @ PatchError::SourceMismatch { .. }) => {
+                        apply_headered(format, header, &data, &patch).map_err(|error| match error {
+                            PatchError::SourceMismatch { .. } => stripped_error,
+                            error => error,
+                        })
+                    }
+                    result => result,
+                },
+            }
+            .map_err(error)?;
+        }
+
+        let mut rom = Self::from_data(Cow::Owned(data), base.copier_header);
+        rom.base_crc = base.crc;
+        Ok(rom)
     }
 
+    /// Use a ROM image in memory as is.
     pub fn new(data: &'rom [u8]) -> Self {
-        let crc = crc32fast::hash(data);
-        let data = Cow::Borrowed(data);
-        Self { data, crc }
+        Self::from_data(Cow::Borrowed(data), false)
+    }
+
+    /// Use a ROM image in memory, skipping the copier header if it has one like
+    /// [`Rom::open`] does.
+    pub fn new_stripping_header(data: &'rom [u8]) -> Self {
+        let copier_header = header::has_copier_header(data);
+        let data = if copier_header {
+            &data[header::COPIER_HEADER_SIZE..]
+        } else {
+            data
+        };
+
+        Self::from_data(Cow::Borrowed(data), copier_header)
     }
 
+    fn from_data(data: Cow<'rom, [u8]>, copier_header: bool) -> Self {
+        let crc = crc32fast::hash(&data);
+        Self {
+            crc,
+            header: SnesHeader::find(&data),
+            data,
+            copier_header,
+            base_crc: crc,
+            map_target: MapTarget::default(),
+        }
+    }
+
+    /// The ROM data, without a copier header.
     pub fn data(&self) -> &[u8] {
         &self.data
     }
 
+    /// CRC32 of the ROM data, so the same for dumps with and without a copier header.
     pub fn crc(&self) -> u32 {
         self.crc
     }
+
+    /// CRC32 of the data as it was opened, before any patches were applied.
+    pub fn base_crc(&self) -> u32 {
+        self.base_crc
+    }
+
+    pub fn map_target(&self) -> MapTarget {
+        self.map_target
+    }
+
+    /// Choose whether ROM maps have to be made for the unpatched or the patched data.
+    pub fn set_map_target(&mut self, map_target: MapTarget) {
+        self.map_target = map_target;
+    }
+
+    /// The CRC32 ROM maps are matched against, see [`Rom::set_map_target`].
+    pub fn map_crc(&self) -> u32 {
+        match self.map_target {
+            MapTarget::Base => self.base_crc,
+            MapTarget::Patched => self.crc,
+        }
+    }
+
+    pub fn header(&self) -> Option<&SnesHeader> {
+        self.header.as_ref()
+    }
+
+    /// Whether a copier header was stripped from the data.
+    pub fn had_copier_header(&self) -> bool {
+        self.copier_header
+    }
+
+    /// How the ROM is mapped according to its header, LoROM if it doesn't have one.
+    pub fn mapping(&self) -> MapMode {
+        self.header
+            .as_ref()
+            .map_or(MapMode::LoRom, |header| header.map_mode)
+    }
+
+    /// The address an offset into the data is usually read from.
+    pub fn address(&self, offset: usize) -> SnesAddress {
+        self.mapping().address(offset)
+    }
+
+    /// Offset into the data an address reads from, if it maps to ROM at all.
+    pub fn offset(&self, address: SnesAddress) -> Option<usize> {
+        self.mapping()
+            .offset(address)
+            .filter(|&offset| offset < self.data.len())
+    }
+
+    /// The checksum of the data, to compare with the one in the [`header`](Rom::header).
+    pub fn checksum(&self) -> u16 {
+        header::checksum(&self.data)
+    }
 }
 
 impl MappedRom {
@@ -90,7 +317,7 @@ impl MappedRom {
     pub fn new_forced(rom: &Rom, map: &RomMap) -> Result<Self, RomError> {
         let metadata = RomMetadata {
             name: "Unknown".to_string(),
-            crc: rom.crc(),
+            crc: rom.map_crc(),
         };
 
         Self::new_inner(&rom.data, map, metadata)
@@ -106,9 +333,15 @@ impl MappedRom {
                 .map(|layout| layout.region)
                 .ok_or_else(|| RomError::InvalidPaletteDefinition(definition.name.clone()))?;
 
-            let mut palette_collection = PaletteCollection::from_compressed(rom, first)?;
+            let decompress_error =
+                |source| RomError::decompress(RegionKind::Palette, &definition.name, map, source);
+
+            let mut palette_collection =
+                PaletteCollection::from_compressed(rom, first).map_err(decompress_error)?;
             for layout in definition.layout.iter().skip(1) {
-                let result = Decompressor::new(rom, layout.region).decompress()?;
+                let result = Decompressor::new(rom, layout.region)
+                    .decompress()
+                    .map_err(decompress_error)?;
 
                 palette_collection.add_palette_data(layout.start, &result.data);
             }
@@ -128,7 +361,10 @@ impl MappedRom {
             let mut tileset = TileSet::new();
 
             for layout in definition.layout.iter() {
-                let partial_tile_set = PartialTileSet::from_compressed(rom, layout.region)?;
+                let partial_tile_set = PartialTileSet::from_compressed(rom, layout.region)
+                    .map_err(|source| {
+                        RomError::decompress(RegionKind::TileSet, &definition.name, map, source)
+                    })?;
                 tileset.add_tile_data(layout.offset, partial_tile_set);
             }
 
@@ -138,9 +374,12 @@ impl MappedRom {
         log::debug!("decompressing rom tilemap data...");
         let mut layout_regions = HashMap::new();
         for definition in map.sprites.iter() {
-            if !layout_regions.contains_key(&definition.layout_region) {
-                let layout = TileMap::from_compressed(rom, definition.layout_region)?;
-                layout_regions.insert(definition.layout_region, Arc::new(layout));
+            if let Entry::Vacant(entry) = layout_regions.entry(definition.layout_region) {
+                let layout =
+                    TileMap::from_compressed(rom, definition.layout_region).map_err(|source| {
+                        RomError::decompress(RegionKind::TileMap, &definition.name, map, source)
+                    })?;
+                entry.insert(Arc::new(layout));
             }
         }
 
@@ -172,10 +411,7 @@ impl MappedRom {
             sprites.push(mapped_sprite);
         }
 
-        let palettes = palettes
-            .into_iter()
-            .map(|(_, palette)| palette)
-            .collect::<Vec<_>>();
+        let palettes = palettes.into_values().collect::<Vec<_>>();
 
         Ok(Self {
             metadata,
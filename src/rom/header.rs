@@ -0,0 +1,176 @@
+//! The internal header every SNES cartridge carries, and the copier headers some dumps are
+//! prefixed with.
+
+use super::MapMode;
+
+/// Size of the header copier devices put in front of the ROM data.
+pub const COPIER_HEADER_SIZE: usize = 0x200;
+
+/// The internal header of a SNES ROM.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SnesHeader {
+    /// where the header was found
+    pub map_mode: MapMode,
+    pub fast_rom: bool,
+    /// up to 21 characters, bytes outside of ASCII are replaced
+    pub title: String,
+    /// what kind of hardware the cartridge contains besides the ROM
+    pub chipset: u8,
+    /// in bytes
+    pub rom_size: usize,
+    /// in bytes, 0 if there is no RAM
+    pub ram_size: usize,
+    /// destination code, see [`SnesHeader::region_name`]
+    pub region: u8,
+    pub version: u8,
+    pub checksum: u16,
+    pub complement: u16,
+}
+
+impl SnesHeader {
+    /// Find the header of `data`, a ROM image without a copier header. Every place a header can
+    /// be is checked for a plausible one, picking the best.
+    pub fn find(data: &[u8]) -> Option<Self> {
+        // the last maximum wins, so ties go to the most common mapping
+        [MapMode::ExHiRom, MapMode::HiRom, MapMode::LoRom]
+            .into_iter()
+            .filter_map(|map_mode| {
+                let header = data.get(map_mode.header_offset()..map_mode.header_offset() + 0x40)?;
+                let score = plausibility(header, map_mode)?;
+                Some((score, map_mode, header))
+            })
+            .max_by_key(|(score, _, _)| *score)
+            .map(|(_, map_mode, header)| Self::parse(header, map_mode))
+    }
+
+    fn parse(header: &[u8], map_mode: MapMode) -> Self {
+        let title = header[..0x15]
+            .iter()
+            .map(|&byte| {
+                if (0x20..0x7f).contains(&byte) {
+                    byte as char
+                } else {
+                    '?'
+                }
+            })
+            .collect::<String>();
+
+        Self {
+            map_mode,
+            fast_rom: header[0x15] & 0x10 != 0,
+            title: title.trim_end().to_string(),
+            chipset: header[0x16],
+            rom_size: size_from_byte(header[0x17]),
+            ram_size: size_from_byte(header[0x18]),
+            region: header[0x19],
+            version: header[0x1b],
+            complement: u16::from_le_bytes([header[0x1c], header[0x1d]]),
+            checksum: u16::from_le_bytes([header[0x1e], header[0x1f]]),
+        }
+    }
+
+    pub fn region_name(&self) -> Option<&'static str> {
+        const NAMES: [&str; 18] = [
+            "Japan",
+            "North America",
+            "Europe",
+            "Scandinavia",
+            "Finland",
+            "Denmark",
+            "France",
+            "Netherlands",
+            "Spain",
+            "Germany",
+            "Italy",
+            "China",
+            "Indonesia",
+            "Korea",
+            "International",
+            "Canada",
+            "Brazil",
+            "Australia",
+        ];
+
+        NAMES.get(self.region as usize).copied()
+    }
+
+    /// Whether the checksum and its complement add up like they should.
+    pub fn complement_matches(&self) -> bool {
+        self.checksum ^ self.complement == 0xffff
+    }
+}
+
+/// Sum of every byte of the image, the way the internal checksum is calculated. Images whose size
+/// isn't a power of two have the part past the largest power of two mirrored until it fills one.
+pub fn checksum(data: &[u8]) -> u16 {
+    if data.is_empty() {
+        return 0;
+    }
+
+    let sum = |data: &[u8]| {
+        data.iter()
+            .fold(0u32, |sum, &byte| sum.wrapping_add(byte as u32))
+    };
+
+    let base = 1 << data.len().ilog2();
+    let remainder = &data[base..];
+    let mut total = sum(&data[..base]);
+    if !remainder.is_empty() {
+        let repeats = base / remainder.len();
+        total = total.wrapping_add(sum(remainder).wrapping_mul(repeats as u32));
+    }
+
+    total as u16
+}
+
+/// Whether `data` starts with a copier header, going by its size being 512 bytes past a multiple
+/// of 1KiB.
+pub fn has_copier_header(data: &[u8]) -> bool {
+    data.len() % 0x400 == COPIER_HEADER_SIZE
+}
+
+/// How much the bytes at a header location look like a header, `None` if they clearly don't.
+fn plausibility(header: &[u8], map_mode: MapMode) -> Option<u32> {
+    let mode = header[0x15];
+    if mode & 0xe0 != 0x20 {
+        return None;
+    }
+
+    let mut score = 0;
+    if map_mode.matches(mode) {
+        score += 2;
+    }
+
+    let complement = u16::from_le_bytes([header[0x1c], header[0x1d]]);
+    let checksum = u16::from_le_bytes([header[0x1e], header[0x1f]]);
+    if checksum ^ complement == 0xffff {
+        score += 4;
+    }
+
+    // half width katakana are fine as well
+    if header[..0x15]
+        .iter()
+        .all(|&byte| (0x20..0x7f).contains(&byte) || (0xa1..0xe0).contains(&byte))
+    {
+        score += 1;
+    }
+
+    if (0x07..=0x0d).contains(&header[0x17]) {
+        score += 1;
+    }
+
+    // the reset vector points into ROM
+    if u16::from_le_bytes([header[0x3c], header[0x3d]]) >= 0x8000 {
+        score += 1;
+    }
+
+    (score >= 3).then_some(score)
+}
+
+/// Sizes are stored as the log2 of the size in KiB.
+fn size_from_byte(byte: u8) -> usize {
+    match byte {
+        1..=0x0f => 0x400 << byte,
+        _ => 0,
+    }
+}
@@ -0,0 +1,367 @@
+//! Writing compressed data back into the ROM, moving it elsewhere if it outgrew its region.
+
+use super::{header, Rom, RomError};
+use crate::{Decompressor, SnesAddress};
+use std::{fs, ops::Range, path::Path};
+
+/// What regions that aren't used anymore get filled with.
+pub const FILL_BYTE: u8 = 0xff;
+
+/// opcode of `LDY #imm`, which is how the game passes the address of compressed data
+const LDY_IMMEDIATE: u8 = 0xa0;
+
+/// Parts of the ROM that can be overwritten, sorted and without overlaps.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct FreeSpace {
+    ranges: Vec<Range<usize>>,
+}
+
+impl FreeSpace {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Runs of at least `min_len` bytes of [`FILL_BYTE`], which is what unused space of a ROM is
+    /// usually padded with.
+    pub fn find(data: &[u8], min_len: usize) -> Self {
+        let mut free = Self::new();
+
+        let mut start = None;
+        for (i, &byte) in data.iter().chain([&!FILL_BYTE]).enumerate() {
+            match (byte == FILL_BYTE, start) {
+                (true, None) => start = Some(i),
+                (false, Some(run_start)) => {
+                    if i - run_start >= min_len {
+                        free.add(run_start..i);
+                    }
+                    start = None;
+                }
+                _ => {}
+            }
+        }
+
+        free
+    }
+
+    pub fn add(&mut self, range: Range<usize>) {
+        if range.is_empty() {
+            return;
+        }
+
+        self.ranges.push(range);
+        self.ranges.sort_by_key(|range| range.start);
+
+        let mut merged: Vec<Range<usize>> = Vec::with_capacity(self.ranges.len());
+        for range in self.ranges.drain(..) {
+            match merged.last_mut() {
+                Some(last) if range.start <= last.end => last.end = last.end.max(range.end),
+                _ => merged.push(range),
+            }
+        }
+        self.ranges = merged;
+    }
+
+    /// Mark `range` as used.
+    pub fn remove(&mut self, range: Range<usize>) {
+        self.ranges = self
+            .ranges
+            .drain(..)
+            .flat_map(|free| {
+                [
+                    free.start..free.end.min(range.start),
+                    free.start.max(range.end)..free.end,
+                ]
+            })
+            .filter(|free| !free.is_empty())
+            .collect();
+    }
+
+    pub fn ranges(&self) -> &[Range<usize>] {
+        &self.ranges
+    }
+
+    /// Bytes free in total.
+    pub fn len(&self) -> usize {
+        self.ranges.iter().map(|range| range.len()).sum()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.ranges.is_empty()
+    }
+
+    /// Take `len` bytes that don't cross a boundary between banks of `bank_size` bytes, since
+    /// the game reads the data through a single bank. Space at the end of the ROM is used
+    /// first, which is where the padding usually is.
+    pub fn allocate(&mut self, len: usize, bank_size: usize) -> Option<usize> {
+        let (index, start) = self
+            .ranges
+            .iter()
+            .enumerate()
+            .rev()
+            .find_map(|(i, range)| {
+                let mut start = range.start;
+                if len > 0 && start / bank_size != (start + len - 1) / bank_size {
+                    start = (start / bank_size + 1) * bank_size;
+                }
+
+                (start + len <= range.end).then_some((i, start))
+            })?;
+
+        Some(self.take(index, start, len))
+    }
+
+    /// Take `len` bytes from inside of `within`, like [`FreeSpace::allocate`].
+    pub fn allocate_in(&mut self, len: usize, within: Range<usize>) -> Option<usize> {
+        let (index, start) = self
+            .ranges
+            .iter()
+            .enumerate()
+            .rev()
+            .find_map(|(i, range)| {
+                let start = range.start.max(within.start);
+                (start + len <= range.end.min(within.end)).then_some((i, start))
+            })?;
+
+        Some(self.take(index, start, len))
+    }
+
+    fn take(&mut self, index: usize, start: usize, len: usize) -> usize {
+        let range = self.ranges.remove(index);
+        for rest in [range.start..start, start + len..range.end] {
+            self.add(rest);
+        }
+
+        start
+    }
+}
+
+/// A reference to compressed data that [`Rom::insert`] changes when the data is moved.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Pointer {
+    /// a 24 bit address at this offset
+    Long(usize),
+    /// a 16 bit address at this offset, with the bank set somewhere else, like the operand of
+    /// the `LDY #$DDBB` the game loads the background tile map with. Data referenced by one of
+    /// these can only be moved within its bank.
+    Short(usize),
+}
+
+impl Pointer {
+    pub fn offset(&self) -> usize {
+        match *self {
+            Pointer::Long(offset) | Pointer::Short(offset) => offset,
+        }
+    }
+}
+
+/// Where [`Rom::insert`] put the data.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Insertion {
+    pub offset: usize,
+    /// whether the data didn't fit its old region anymore
+    pub relocated: bool,
+    /// the pointers that were changed to point at the new location
+    pub pointers: Vec<Pointer>,
+}
+
+impl Rom<'_> {
+    /// Overwrite the data at `offset`.
+    pub fn write(&mut self, offset: usize, bytes: &[u8]) {
+        self.copy(offset, bytes);
+        self.update_crc();
+    }
+
+    /// Overwrite the data without updating the CRC, for changes made in several steps.
+    fn copy(&mut self, offset: usize, bytes: &[u8]) {
+        self.data.to_mut()[offset..offset + bytes.len()].copy_from_slice(bytes);
+    }
+
+    fn update_crc(&mut self) {
+        self.crc = crc32fast::hash(&self.data);
+    }
+
+    /// Write the ROM data to a file, without a copier header.
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> Result<(), RomError> {
+        fs::write(path, &self.data)?;
+        Ok(())
+    }
+
+    /// Write the checksum of the data into the header, returning it. Does nothing if the ROM has
+    /// no header.
+    pub fn fix_checksum(&mut self) -> Option<u16> {
+        let offset = self.header.as_ref()?.map_mode.header_offset();
+
+        // checksum and complement always add the same to the sum
+        self.copy(offset + 0x1c, &[0xff, 0xff, 0x00, 0x00]);
+        let checksum = header::checksum(&self.data);
+
+        let mut fields = [0; 4];
+        fields[..2].copy_from_slice(&(!checksum).to_le_bytes());
+        fields[2..].copy_from_slice(&checksum.to_le_bytes());
+        self.copy(offset + 0x1c, &fields);
+        self.update_crc();
+
+        let header = self.header.as_mut()?;
+        header.checksum = checksum;
+        header.complement = !checksum;
+
+        Some(checksum)
+    }
+
+    /// Places that look like they point at `offset`: 24 bit addresses through any of the banks
+    /// that map it and `LDY` instructions loading its 16 bit address. Any data can look like
+    /// this by chance, so these are only candidates to check before passing them to
+    /// [`Rom::insert`].
+    pub fn pointers_to(&self, offset: usize) -> Vec<Pointer> {
+        let patterns = self
+            .mirrors(offset)
+            .into_iter()
+            .map(pointer_bytes)
+            .collect::<Vec<_>>();
+        let [low, high] = self.address(offset).addr.to_le_bytes();
+
+        self.data
+            .windows(3)
+            .enumerate()
+            .filter_map(|(i, bytes)| {
+                if patterns.iter().any(|pattern| pattern == bytes) {
+                    Some(Pointer::Long(i))
+                } else if bytes == [LDY_IMMEDIATE, low, high] {
+                    Some(Pointer::Short(i + 1))
+                } else {
+                    None
+                }
+            })
+            .collect()
+    }
+
+    /// Whether `pointer` currently points at `offset`.
+    fn points_to(&self, pointer: Pointer, offset: usize) -> bool {
+        let bytes = |len| self.data.get(pointer.offset()..pointer.offset() + len);
+        match pointer {
+            Pointer::Long(_) => bytes(3).is_some_and(|bytes| {
+                self.mirrors(offset)
+                    .into_iter()
+                    .any(|address| pointer_bytes(address) == bytes)
+            }),
+            Pointer::Short(_) => bytes(2).is_some_and(|bytes| {
+                u16::from_le_bytes([bytes[0], bytes[1]]) == self.address(offset).addr
+            }),
+        }
+    }
+
+    /// Every address that reads from `offset`.
+    fn mirrors(&self, offset: usize) -> Vec<SnesAddress> {
+        let addr = self.address(offset).addr;
+        (0..=0xff)
+            .map(|bank| SnesAddress::new(bank, addr))
+            .filter(|&address| self.mapping().offset(address) == Some(offset))
+            .collect()
+    }
+
+    /// Replace the compressed data of the region at `region` with `compressed`. The data stays
+    /// where it is if it fits, otherwise it is moved to space taken from `free` and `pointers`
+    /// are changed to point to the new location. Moving the data is refused if no pointers are
+    /// given, or one of them doesn't point at the region. Space the data doesn't use anymore is
+    /// filled with [`FILL_BYTE`] and given to `free`.
+    ///
+    /// Doesn't update the checksum, see [`Rom::fix_checksum`].
+    pub fn insert(
+        &mut self,
+        region: usize,
+        compressed: &[u8],
+        pointers: &[Pointer],
+        free: &mut FreeSpace,
+    ) -> Result<Insertion, RomError> {
+        let insertion = self.place(region, compressed, pointers, free)?;
+        self.update_crc();
+        Ok(insertion)
+    }
+
+    fn place(
+        &mut self,
+        region: usize,
+        compressed: &[u8],
+        pointers: &[Pointer],
+        free: &mut FreeSpace,
+    ) -> Result<Insertion, RomError> {
+        let old_len = Decompressor::new(&self.data, region)
+            .decompress()
+            .map_err(|source| RomError::InvalidRegion {
+                offset: region,
+                source,
+            })?
+            .bytes_read;
+        // the end of the stream may look like padding
+        free.remove(region..region + old_len);
+
+        if compressed.len() <= old_len {
+            self.copy(region, compressed);
+            self.release(region + compressed.len()..region + old_len, free);
+
+            return Ok(Insertion {
+                offset: region,
+                relocated: false,
+                pointers: Vec::new(),
+            });
+        }
+
+        if pointers.is_empty() {
+            return Err(RomError::NoPointers(region));
+        }
+        if let Some(&pointer) = pointers.iter().find(|&&p| !self.points_to(p, region)) {
+            return Err(RomError::NotAPointer {
+                pointer: pointer.offset(),
+                region,
+            });
+        }
+
+        let bank_size = self.mapping().bank_size();
+        let offset = if pointers.iter().any(|p| matches!(p, Pointer::Short(_))) {
+            let bank = region / bank_size * bank_size;
+            free.allocate_in(compressed.len(), bank..bank + bank_size)
+        } else {
+            free.allocate(compressed.len(), bank_size)
+        }
+        .ok_or(RomError::NoFreeSpace(compressed.len()))?;
+
+        self.copy(offset, compressed);
+
+        let new_mirrors = self.mirrors(offset);
+        for &pointer in pointers {
+            match pointer {
+                Pointer::Long(pointer) => {
+                    // stay in the same kind of bank, e.g. FastROM banks for LoROM
+                    let old_bank = self.data[pointer + 2];
+                    let address = new_mirrors
+                        .iter()
+                        .copied()
+                        .find(|address| address.bank & 0xc0 == old_bank & 0xc0)
+                        .unwrap_or_else(|| self.address(offset));
+                    self.copy(pointer, &pointer_bytes(address));
+                }
+                Pointer::Short(pointer) => {
+                    self.copy(pointer, &self.address(offset).addr.to_le_bytes());
+                }
+            }
+        }
+
+        self.release(region..region + old_len, free);
+
+        Ok(Insertion {
+            offset,
+            relocated: true,
+            pointers: pointers.to_vec(),
+        })
+    }
+
+    fn release(&mut self, range: Range<usize>, free: &mut FreeSpace) {
+        self.copy(range.start, &vec![FILL_BYTE; range.len()]);
+        free.add(range);
+    }
+}
+
+fn pointer_bytes(address: SnesAddress) -> [u8; 3] {
+    let [low, high] = address.addr.to_le_bytes();
+    [low, high, address.bank]
+}
@@ -1,6 +1,10 @@
-use crate::Rom;
-use serde::Deserialize;
-use std::sync::{Arc, LazyLock};
+use super::{MapMode, RomError};
+use crate::{CompressionStats, Decompressor, Rom, SnesAddress};
+use serde::{de::Error as _, Deserialize, Serialize};
+use std::{
+    fmt,
+    sync::{Arc, LazyLock},
+};
 
 static INBUILT_MAPS: LazyLock<Vec<Arc<RomMap>>> = LazyLock::new(|| {
     const INBUILT_MAP_SRC: &[&str] = &[include_str!("panepon_map.toml")];
@@ -13,6 +17,9 @@ static INBUILT_MAPS: LazyLock<Vec<Arc<RomMap>>> = LazyLock::new(|| {
 #[derive(Debug, Clone, Deserialize)]
 pub struct RomMap {
     pub supported_roms: Vec<RomMetadata>,
+    /// what the SNES addresses in the map refer to
+    #[serde(default)]
+    pub mapping: MapMode,
 
     #[serde(rename = "palette")]
     pub palettes: Vec<PaletteDefinition>,
@@ -23,8 +30,42 @@ pub struct RomMap {
 }
 
 impl RomMap {
+    /// Regions can be given as offsets into the image or as the SNES addresses they are read
+    /// from, which get converted to offsets according to `mapping`.
     pub fn parse(map: &str) -> Result<RomMap, toml::de::Error> {
-        toml::de::from_str(map)
+        let mut map: RomMap = toml::de::from_str(map)?;
+
+        let mapping = map.mapping;
+        let resolve = |location: Location| {
+            location
+                .offset(mapping)
+                .ok_or_else(|| toml::de::Error::custom(format!("{} doesn't map to ROM", location)))
+        };
+
+        for layout in map
+            .palettes
+            .iter_mut()
+            .flat_map(|def| def.layout.iter_mut())
+        {
+            layout.region = resolve(layout.location)?;
+        }
+        for layout in map
+            .tilesets
+            .iter_mut()
+            .flat_map(|def| def.layout.iter_mut())
+        {
+            layout.region = resolve(layout.location)?;
+        }
+        for sprite in map.sprites.iter_mut() {
+            sprite.layout_region = resolve(sprite.layout_location)?;
+        }
+
+        Ok(map)
+    }
+
+    /// The address an offset is read from according to the mapping of the map.
+    pub fn address(&self, offset: usize) -> SnesAddress {
+        self.mapping.address(offset)
     }
 
     pub fn is_compatible_with(&self, rom: &Rom) -> bool {
@@ -34,22 +75,124 @@ impl RomMap {
     pub fn get_compatible_metadata(&self, rom: &Rom) -> Option<RomMetadata> {
         self.supported_roms
             .iter()
-            .find(|rom_type| rom_type.crc == rom.crc())
+            .find(|rom_type| rom_type.crc == rom.map_crc())
             .cloned()
     }
 
-    pub fn find_inbuilt_for<'rom>(rom: &Rom) -> Option<Arc<RomMap>> {
+    /// All compressed regions referenced by the map, sorted by their offset.
+    /// Regions used by multiple definitions are only listed once, under the first name using them.
+    pub fn regions(&self) -> Vec<MapRegion> {
+        let palettes = self.palettes.iter().flat_map(|definition| {
+            definition.layout.iter().map(|layout| MapRegion {
+                offset: layout.region,
+                kind: RegionKind::Palette,
+                name: definition.name.clone(),
+            })
+        });
+        let tilesets = self.tilesets.iter().flat_map(|definition| {
+            definition.layout.iter().map(|layout| MapRegion {
+                offset: layout.region,
+                kind: RegionKind::TileSet,
+                name: definition.name.clone(),
+            })
+        });
+        let tilemaps = self.sprites.iter().map(|definition| MapRegion {
+            offset: definition.layout_region,
+            kind: RegionKind::TileMap,
+            name: definition.name.clone(),
+        });
+
+        let mut regions: Vec<MapRegion> = Vec::new();
+        for region in palettes.chain(tilesets).chain(tilemaps) {
+            if !regions.iter().any(|known| known.offset == region.offset) {
+                regions.push(region);
+            }
+        }
+        regions.sort_by_key(|region| region.offset);
+
+        regions
+    }
+
+    /// Decode every region of the map and collect its [`CompressionStats`].
+    pub fn stats(&self, rom: &Rom) -> Result<Vec<RegionStats>, RomError> {
+        self.regions()
+            .into_iter()
+            .map(|region| {
+                let (trace, result) =
+                    Decompressor::new(rom.data(), region.offset).decompress_traced();
+                let result = result.map_err(|source| {
+                    RomError::decompress(region.kind, &region.name, self, source)
+                })?;
+
+                let stats = CompressionStats::from_trace(&trace, &result);
+                Ok(RegionStats {
+                    ratio: stats.ratio(),
+                    region,
+                    stats,
+                })
+            })
+            .collect()
+    }
+
+    pub fn find_inbuilt_for(rom: &Rom) -> Option<Arc<RomMap>> {
         INBUILT_MAPS
             .iter()
             .find(|map| {
                 map.supported_roms
                     .iter()
-                    .any(|rom_type| rom_type.crc == rom.crc())
+                    .any(|rom_type| rom_type.crc == rom.map_crc())
             })
             .cloned()
     }
 }
 
+/// Which data a ROM map has to be made for to be used with a patched [`Rom`].
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum MapTarget {
+    /// the ROM before the patches were applied, for patches that don't move the graphics
+    #[default]
+    Base,
+    Patched,
+}
+
+/// The kind of data stored in a compressed region.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum RegionKind {
+    Palette,
+    TileSet,
+    TileMap,
+}
+
+/// A compressed region referenced by a [`RomMap`].
+#[derive(Debug, Clone, Serialize)]
+pub struct MapRegion {
+    pub offset: usize,
+    pub kind: RegionKind,
+    pub name: String,
+}
+
+/// The [`CompressionStats`] of a single region, see [`RomMap::stats`].
+#[derive(Debug, Clone, Serialize)]
+pub struct RegionStats {
+    #[serde(flatten)]
+    pub region: MapRegion,
+    /// see [`CompressionStats::ratio`]
+    pub ratio: f64,
+    #[serde(flatten)]
+    pub stats: CompressionStats,
+}
+
+impl fmt::Display for RegionKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            RegionKind::Palette => write!(f, "palette"),
+            RegionKind::TileSet => write!(f, "tileset"),
+            RegionKind::TileMap => write!(f, "tilemap"),
+        }
+    }
+}
+
 #[derive(Debug, Clone, Deserialize)]
 pub struct RomMetadata {
     pub name: String,
@@ -64,6 +207,9 @@ pub struct PaletteDefinition {
 
 #[derive(Debug, Clone, Deserialize)]
 pub struct PaletteLayout {
+    #[serde(rename = "region")]
+    location: Location,
+    #[serde(skip)]
     pub region: usize,
 
     #[serde(default)]
@@ -81,6 +227,9 @@ pub struct SpriteDefinition {
     pub tileset: String,
     pub palette: String,
 
+    #[serde(rename = "layout-region")]
+    layout_location: Location,
+    #[serde(skip)]
     pub layout_region: usize,
 }
 
@@ -92,6 +241,62 @@ pub struct TileSetDefinition {
 
 #[derive(Debug, Clone, Deserialize)]
 pub struct TileSetLayout {
+    #[serde(rename = "region")]
+    location: Location,
+    #[serde(skip)]
     pub region: usize,
     pub offset: usize,
 }
+
+/// Where a region starts, as written in the map.
+#[derive(Debug, Clone, Copy, Deserialize)]
+#[serde(try_from = "LocationValue")]
+enum Location {
+    Offset(usize),
+    Address(SnesAddress),
+}
+
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum LocationValue {
+    Offset(usize),
+    Text(String),
+}
+
+impl Location {
+    fn offset(self, mapping: MapMode) -> Option<usize> {
+        match self {
+            Location::Offset(offset) => Some(offset),
+            Location::Address(address) => mapping.offset(address),
+        }
+    }
+}
+
+impl TryFrom<LocationValue> for Location {
+    type Error = String;
+
+    fn try_from(value: LocationValue) -> Result<Self, Self::Error> {
+        match value {
+            LocationValue::Offset(offset) => Ok(Location::Offset(offset)),
+            LocationValue::Text(text) => match text.strip_prefix("0x") {
+                Some(hex) => usize::from_str_radix(hex, 16).map(Location::Offset).ok(),
+                None => text.parse().map(Location::Address).ok(),
+            }
+            .ok_or_else(|| {
+                format!(
+                    "invalid region '{}', expected an offset or an address like $91:DDBB",
+                    text
+                )
+            }),
+        }
+    }
+}
+
+impl fmt::Display for Location {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Location::Offset(offset) => write!(f, "{:#x}", offset),
+            Location::Address(address) => write!(f, "{}", address),
+        }
+    }
+}
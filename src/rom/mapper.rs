@@ -0,0 +1,94 @@
+//! Converting between CPU addresses and offsets into the ROM image.
+
+use crate::SnesAddress;
+use serde::Deserialize;
+use std::fmt;
+
+/// How the cartridge maps its ROM into the address space, which also decides where its
+/// header lives.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum MapMode {
+    #[default]
+    LoRom,
+    HiRom,
+    ExHiRom,
+}
+
+impl MapMode {
+    /// Offset of the header in the ROM image.
+    pub fn header_offset(self) -> usize {
+        match self {
+            MapMode::LoRom => 0x7fc0,
+            MapMode::HiRom => 0xffc0,
+            MapMode::ExHiRom => 0x40ffc0,
+        }
+    }
+
+    /// Size of the blocks of contiguous ROM each bank maps.
+    pub fn bank_size(self) -> usize {
+        match self {
+            MapMode::LoRom => 0x8000,
+            MapMode::HiRom | MapMode::ExHiRom => 0x10000,
+        }
+    }
+
+    /// Whether the low nibble of the map mode byte in the header matches this mapping.
+    pub(super) fn matches(self, map_mode: u8) -> bool {
+        match self {
+            MapMode::LoRom => matches!(map_mode & 0xf, 0x0 | 0x2 | 0x3),
+            MapMode::HiRom => matches!(map_mode & 0xf, 0x1 | 0xa),
+            MapMode::ExHiRom => map_mode & 0xf == 0x5,
+        }
+    }
+
+    /// Offset into the image the address reads from, if it maps to ROM at all.
+    ///
+    /// HiROM maps 64KiB banks to $C0-$FF and $40-$7D, with the upper halves mirrored to
+    /// $80-$BF and $00-$3F. ExHiROM continues the image from $C0-$FF in $40-$7D and $00-$3F.
+    pub fn offset(self, address: SnesAddress) -> Option<usize> {
+        let SnesAddress { bank, addr } = address;
+        let bank_offset = ((bank & 0x3f) as usize) << 16 | addr as usize;
+
+        match self {
+            MapMode::LoRom => address.lorom_offset(),
+            _ if matches!(bank, 0x7e | 0x7f) => None,
+            _ if bank & 0x40 == 0 && addr < 0x8000 => None,
+            MapMode::HiRom => Some(bank_offset),
+            MapMode::ExHiRom if bank & 0x80 == 0 => Some(0x400000 + bank_offset),
+            MapMode::ExHiRom => Some(bank_offset),
+        }
+    }
+
+    /// The address an offset into the image is usually read from, in the banks that map whole
+    /// 64KiB of ROM for HiROM. LoROM uses the banks starting at $00, except for the last two
+    /// which hold WRAM there.
+    pub fn address(self, offset: usize) -> SnesAddress {
+        match self {
+            MapMode::LoRom => {
+                let address = SnesAddress::from_lorom_offset(offset);
+                match address.bank {
+                    0x7e | 0x7f => SnesAddress::new(address.bank | 0x80, address.addr),
+                    _ => address,
+                }
+            }
+            MapMode::HiRom => SnesAddress::new(0xc0 | (offset >> 16) as u8, offset as u16),
+            MapMode::ExHiRom if offset < 0x400000 => {
+                SnesAddress::new(0xc0 | (offset >> 16) as u8, offset as u16)
+            }
+            MapMode::ExHiRom => {
+                SnesAddress::new(0x40 | ((offset - 0x400000) >> 16) as u8, offset as u16)
+            }
+        }
+    }
+}
+
+impl fmt::Display for MapMode {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            MapMode::LoRom => write!(f, "LoROM"),
+            MapMode::HiRom => write!(f, "HiROM"),
+            MapMode::ExHiRom => write!(f, "ExHiROM"),
+        }
+    }
+}
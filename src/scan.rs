@@ -0,0 +1,308 @@
+//! Searching a ROM for compressed data that isn't in the map yet.
+
+use crate::{ChainTable, CompressionStats, DecompressOptions, Decompressor, RegionKind};
+use rayon::prelude::*;
+use std::{io, ops::Range};
+
+mod cache;
+pub use cache::ScanCache;
+mod draft;
+pub use draft::draft_map;
+mod score;
+pub use score::Score;
+
+/// Offsets handed to the decoder at once, which is how much work an interrupted scan with a
+/// [`ScanCache`] loses at most and how often results get passed on.
+const CHUNK_SIZE: usize = 0x10000;
+
+/// An offset that decompressed to something, before looking at the data.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ScanHit {
+    pub offset: usize,
+    /// size of the decompressed data
+    pub length: usize,
+    pub bytes_read: usize,
+}
+
+/// A stream that decompressed successfully.
+#[derive(Debug, Clone)]
+pub struct ScanResult {
+    pub offset: usize,
+    /// offset right after the compressed data
+    pub end: usize,
+    /// what the data looks like, `None` if it doesn't look like anything in particular
+    pub kind: Option<RegionKind>,
+    pub score: Score,
+    pub data: Vec<u8>,
+    /// later offsets that enter the same stream partway through, see [`deduplicate`]
+    pub aliases: Vec<usize>,
+}
+
+/// Guesses what kind of data a decompressed stream holds.
+#[derive(Debug, Clone)]
+pub struct Classifier {
+    /// tile sets need at least this many tiles, and at most the 1024 a tile set can hold
+    pub min_tiles: usize,
+    /// tile maps need at least this many entries
+    pub min_tilemap_entries: usize,
+    /// how many different palettes the entries of a tile map may use
+    pub max_tilemap_palettes: usize,
+}
+
+impl Default for Classifier {
+    fn default() -> Self {
+        Self {
+            min_tiles: 16,
+            min_tilemap_entries: 16,
+            max_tilemap_palettes: 4,
+        }
+    }
+}
+
+impl Classifier {
+    /// The kinds are checked in the order palette, tile set, tile map, so short tile maps that
+    /// don't flip anything vertically are taken for palettes.
+    pub fn classify(&self, data: &[u8]) -> Option<RegionKind> {
+        // runs of a single value decode from almost anything and fit every kind
+        if data.iter().all(|&value| value == data[0]) {
+            return None;
+        }
+
+        if self.is_palette(data) {
+            Some(RegionKind::Palette)
+        } else if self.is_tileset(data) {
+            Some(RegionKind::TileSet)
+        } else if self.is_tilemap(data) {
+            Some(RegionKind::TileMap)
+        } else {
+            None
+        }
+    }
+
+    /// Up to 16 palettes of 16 BGR555 colors, which leave the top bit clear.
+    fn is_palette(&self, data: &[u8]) -> bool {
+        data.len().is_multiple_of(32)
+            && data.len() <= 512
+            && data.chunks_exact(2).all(|color| color[1] & 0x80 == 0)
+    }
+
+    fn is_tileset(&self, data: &[u8]) -> bool {
+        let tiles = data.len() / 32;
+
+        data.len().is_multiple_of(32) && tiles >= self.min_tiles && tiles <= 1024
+    }
+
+    fn is_tilemap(&self, data: &[u8]) -> bool {
+        if !data.len().is_multiple_of(2) || data.len() / 2 < self.min_tilemap_entries {
+            return false;
+        }
+
+        let palettes = data
+            .chunks_exact(2)
+            .fold(0u8, |used, entry| used | 1 << ((entry[1] >> 2) & 0x7));
+
+        palettes.count_ones() as usize <= self.max_tilemap_palettes
+    }
+}
+
+/// Decompress every offset of `src` that the [`ChainTable`] doesn't rule out and classify the
+/// data. `progress` is called with the number of offsets dealt with since the last call, adding up
+/// to the length of `src`. The results are [deduplicated](deduplicate) and sorted by their offset.
+/// Each one is also handed to `on_result` while the scan goes on, once no later offset can be an
+/// alias of it anymore.
+pub fn scan(
+    src: &[u8],
+    options: &DecompressOptions,
+    classifier: &Classifier,
+    progress: impl Fn(usize) + Sync,
+    on_result: impl FnMut(&ScanResult),
+) -> Vec<ScanResult> {
+    let chains = ChainTable::new(src);
+    let mut results = Deduplicator::new(on_result);
+    for chunk in chunks(0..src.len()) {
+        let hits = find_streams(src, &chains, options, chunk, &progress);
+        results.extend(evaluate(src, options, classifier, &hits));
+    }
+
+    results.finish()
+}
+
+/// Like [`scan`], but only decompresses the offsets the cache doesn't know about yet, saving
+/// what it finds as it goes.
+pub fn scan_cached(
+    src: &[u8],
+    options: &DecompressOptions,
+    classifier: &Classifier,
+    cache: &mut ScanCache,
+    progress: impl Fn(usize) + Sync,
+    on_result: impl FnMut(&ScanResult),
+) -> io::Result<Vec<ScanResult>> {
+    let start = cache.done().min(src.len());
+    if start > 0 && start < src.len() {
+        log::info!("Resuming scan at {:#07x}", start);
+    }
+    progress(start);
+
+    let mut results = Deduplicator::new(on_result);
+    for hits in cache.hits().chunks(CHUNK_SIZE) {
+        results.extend(evaluate(src, options, classifier, hits));
+    }
+
+    if start < src.len() {
+        let chains = ChainTable::new(src);
+        for chunk in chunks(start..src.len()) {
+            let hits = find_streams(src, &chains, options, chunk.clone(), &progress);
+            cache.append(&hits, chunk.end)?;
+            results.extend(evaluate(src, options, classifier, &hits));
+        }
+    }
+
+    Ok(results.finish())
+}
+
+fn chunks(range: Range<usize>) -> impl Iterator<Item = Range<usize>> {
+    range
+        .clone()
+        .step_by(CHUNK_SIZE)
+        .map(move |start| start..(start + CHUNK_SIZE).min(range.end))
+}
+
+/// Every offset in `range` that decodes to something, sorted by offset.
+fn find_streams(
+    src: &[u8],
+    chains: &ChainTable,
+    options: &DecompressOptions,
+    range: Range<usize>,
+    progress: &(impl Fn(usize) + Sync),
+) -> Vec<ScanHit> {
+    let candidates = range
+        .clone()
+        .filter(|&offset| chains.may_decompress(offset, options))
+        .collect::<Vec<_>>();
+
+    log::debug!(
+        "{} of {} offsets might hold compressed data",
+        candidates.len(),
+        range.len()
+    );
+    progress(range.len() - candidates.len());
+
+    candidates
+        .into_par_iter()
+        .filter_map(|offset| {
+            let result = Decompressor::with_options(src, offset, options.clone()).decompress_fast();
+            progress(1);
+
+            let result = result.ok().filter(|result| !result.data.is_empty())?;
+            Some(ScanHit {
+                offset,
+                length: result.data.len(),
+                bytes_read: result.bytes_read,
+            })
+        })
+        .collect()
+}
+
+/// Decode the hits again with a trace to classify and score them, keeping their order.
+fn evaluate(
+    src: &[u8],
+    options: &DecompressOptions,
+    classifier: &Classifier,
+    hits: &[ScanHit],
+) -> Vec<ScanResult> {
+    hits.par_iter()
+        .filter_map(|hit| {
+            let (trace, result) =
+                Decompressor::with_options(src, hit.offset, options.clone()).decompress_traced();
+            // a cache from a different ROM with the same CRC is unlikely but not impossible
+            let result = result
+                .ok()
+                .filter(|result| result.data.len() == hit.length)?;
+            let kind = classifier.classify(&result.data);
+            let stats = CompressionStats::from_trace(&trace, &result);
+
+            Some(ScanResult {
+                offset: hit.offset,
+                end: hit.offset + result.bytes_read,
+                kind,
+                score: Score::new(&result.data, kind, &stats),
+                data: result.data,
+                aliases: Vec::new(),
+            })
+        })
+        .collect()
+}
+
+/// Merge results that are the same stream entered partway through into the one starting
+/// earliest. Those end at the same offset and decode to a suffix of its output. Garbage right
+/// before a stream can decode into it as well, in which case the real start is among the
+/// aliases. A result with a different kind is kept on its own, so nothing that looks like
+/// something gets hidden behind a stream that doesn't.
+pub fn deduplicate(mut results: Vec<ScanResult>) -> Vec<ScanResult> {
+    results.sort_by_key(|result| result.offset);
+
+    let mut deduplicator = Deduplicator::new(|_: &ScanResult| {});
+    deduplicator.extend(results);
+    deduplicator.finish()
+}
+
+/// Does what [`deduplicate`] does for results coming in by offset. A result is done once the
+/// offsets reach its end, since its aliases start before that.
+struct Deduplicator<F> {
+    /// sorted by offset
+    pending: Vec<ScanResult>,
+    done: Vec<ScanResult>,
+    on_result: F,
+}
+
+impl<F: FnMut(&ScanResult)> Deduplicator<F> {
+    fn new(on_result: F) -> Self {
+        Self {
+            pending: Vec::new(),
+            done: Vec::new(),
+            on_result,
+        }
+    }
+
+    fn push(&mut self, result: ScanResult) {
+        self.finish_until(result.offset);
+
+        let canonical = self.pending.iter_mut().find(|canonical| {
+            canonical.end == result.end
+                && (result.kind.is_none() || result.kind == canonical.kind)
+                && canonical.data.ends_with(&result.data)
+        });
+
+        match canonical {
+            Some(canonical) => canonical.aliases.push(result.offset),
+            None => self.pending.push(result),
+        }
+    }
+
+    /// Pass on the results ending at or before `offset`, as far as that keeps them in order.
+    fn finish_until(&mut self, offset: usize) {
+        let done = self
+            .pending
+            .iter()
+            .take_while(|result| result.end <= offset)
+            .count();
+
+        for result in self.pending.drain(..done) {
+            (self.on_result)(&result);
+            self.done.push(result);
+        }
+    }
+
+    fn finish(mut self) -> Vec<ScanResult> {
+        self.finish_until(usize::MAX);
+        self.done
+    }
+}
+
+impl<F: FnMut(&ScanResult)> Extend<ScanResult> for Deduplicator<F> {
+    fn extend<I: IntoIterator<Item = ScanResult>>(&mut self, results: I) {
+        for result in results {
+            self.push(result);
+        }
+    }
+}
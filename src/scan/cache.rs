@@ -0,0 +1,140 @@
+//! Raw scan results saved to disk, so scans can be evaluated again and resumed.
+//!
+//! The file is plain text, starting with a header naming what the results depend on:
+//!
+//! ```text
+//! thanatos scan cache
+//! crc 74427d47
+//! decoder 1
+//! options DecompressOptions { max_output: 32768, ... }
+//! ```
+//!
+//! followed by a `hit <offset> <length> <bytes read>` line per stream and a `done <offset>` line
+//! once every offset before it has been dealt with. Anything after the last `done` line is from
+//! a scan that was cut short and gets dropped.
+
+use super::ScanHit;
+use crate::{DecompressOptions, DECODER_VERSION};
+use std::{
+    fs::{self, File, OpenOptions},
+    io::{self, Write},
+    path::Path,
+};
+
+const MAGIC: &str = "thanatos scan cache";
+
+#[derive(Debug)]
+pub struct ScanCache {
+    file: File,
+    hits: Vec<ScanHit>,
+    done: usize,
+}
+
+impl ScanCache {
+    /// Open the cache at `path`, keeping the results in it if they were made for the same ROM, by
+    /// the same decoder and with the same options. Otherwise the file is started over.
+    pub fn open(path: impl AsRef<Path>, crc: u32, options: &DecompressOptions) -> io::Result<Self> {
+        let path = path.as_ref();
+        let header = format!(
+            "{}\ncrc {:08x}\ndecoder {}\noptions {:?}\n",
+            MAGIC, crc, DECODER_VERSION, options
+        );
+
+        let (hits, done, valid_len) = match fs::read(path) {
+            Ok(contents) => match std::str::from_utf8(&contents)
+                .ok()
+                .and_then(|contents| contents.strip_prefix(&header))
+            {
+                Some(body) => {
+                    let (hits, done, body_len) = parse(body);
+                    (hits, done, header.len() + body_len)
+                }
+                None => {
+                    log::warn!("Scan cache {} is outdated, starting over", path.display());
+                    (Vec::new(), 0, 0)
+                }
+            },
+            Err(err) if err.kind() == io::ErrorKind::NotFound => (Vec::new(), 0, 0),
+            Err(err) => return Err(err),
+        };
+
+        let mut file = OpenOptions::new().create(true).append(true).open(path)?;
+        file.set_len(valid_len as u64)?;
+        if valid_len == 0 {
+            file.write_all(header.as_bytes())?;
+        }
+
+        Ok(Self { file, hits, done })
+    }
+
+    /// Every stream found so far, sorted by offset.
+    pub fn hits(&self) -> &[ScanHit] {
+        &self.hits
+    }
+
+    /// Every offset before this one has been scanned.
+    pub fn done(&self) -> usize {
+        self.done
+    }
+
+    /// Save the streams found up to `done`, which have to come after the ones saved before.
+    pub fn append(&mut self, hits: &[ScanHit], done: usize) -> io::Result<()> {
+        let mut lines = String::new();
+        for hit in hits {
+            lines += &format!("hit {} {} {}\n", hit.offset, hit.length, hit.bytes_read);
+        }
+        lines += &format!("done {}\n", done);
+
+        self.file.write_all(lines.as_bytes())?;
+        self.file.flush()?;
+
+        self.hits.extend_from_slice(hits);
+        self.done = done;
+
+        Ok(())
+    }
+}
+
+/// The hits and progress recorded in `body`, and how many of its bytes are worth keeping.
+fn parse(body: &str) -> (Vec<ScanHit>, usize, usize) {
+    let mut hits = Vec::new();
+    let mut pending = Vec::new();
+    let mut done = 0;
+    let mut valid_len = 0;
+
+    let mut position = 0;
+    for line in body.split_inclusive('\n') {
+        position += line.len();
+        let Some(line) = line.strip_suffix('\n') else {
+            break;
+        };
+
+        let fields = line.split(' ').collect::<Vec<_>>();
+        match fields.as_slice() {
+            ["hit", offset, length, bytes_read] => {
+                let hit = (|| {
+                    Some(ScanHit {
+                        offset: offset.parse().ok()?,
+                        length: length.parse().ok()?,
+                        bytes_read: bytes_read.parse().ok()?,
+                    })
+                })();
+                match hit {
+                    Some(hit) => pending.push(hit),
+                    None => break,
+                }
+            }
+            ["done", until] => match until.parse() {
+                Ok(until) => {
+                    hits.append(&mut pending);
+                    done = until;
+                    valid_len = position;
+                }
+                Err(_) => break,
+            },
+            _ => break,
+        }
+    }
+
+    (hits, done, valid_len)
+}
@@ -0,0 +1,192 @@
+//! Turning scan results into a [`RomMap`](crate::RomMap) to start curating from.
+
+use super::ScanResult;
+use crate::RegionKind;
+use std::fmt::Write;
+
+/// Size of the palette data a `[[palette]]` entry has to start with.
+const FULL_PALETTES: usize = 512;
+
+/// Write a map listing every classified result as a `[[palette]]`, `[[tileset]]` or `[[sprite]]`
+/// that [`RomMap::parse`](crate::RomMap::parse) accepts. Regions are named after their offset.
+/// Sprites get the size guessed from the length of their tile map, the smallest tile set
+/// holding every tile they use and the closest palette, ties going to the one closest to the
+/// tile map. Anything that can't be used as is gets written as a comment instead: sprites
+/// without a fitting tile set or palette, and palette data too short to stand on its own, which
+/// needs a full set of palettes under it and the index it starts at.
+pub fn draft_map(results: &[ScanResult], name: &str, crc: u32) -> String {
+    let of_kind = |kind| {
+        results
+            .iter()
+            .filter(move |result| result.kind == Some(kind))
+    };
+    let (palettes, partial_palettes): (Vec<_>, Vec<_>) =
+        of_kind(RegionKind::Palette).partition(|palette| palette.data.len() == FULL_PALETTES);
+    let tilesets = of_kind(RegionKind::TileSet).collect::<Vec<_>>();
+    let tilemaps = of_kind(RegionKind::TileMap).collect::<Vec<_>>();
+    let sprites = tilemaps
+        .iter()
+        .map(|tilemap| draft_sprite(tilemap, &tilesets, &palettes))
+        .collect::<Vec<_>>();
+
+    let mut map = String::new();
+    writeln!(
+        map,
+        "supported_roms = [\n    {{ name = {:?}, crc = {:#010X} }},\n]",
+        name, crc
+    )
+    .unwrap();
+
+    // a table array can't be written as a key once the tables start
+    for (key, empty) in [
+        ("palette", palettes.is_empty()),
+        ("tileset", tilesets.is_empty()),
+        ("sprite", !sprites.iter().any(Result::is_ok)),
+    ] {
+        if empty {
+            writeln!(map, "{} = []", key).unwrap();
+        }
+    }
+
+    for palette in &palettes {
+        writeln!(
+            map,
+            "\n[[palette]]\nname = \"{}\"\nlayout = [\n    {{ region = {:#x} }}, # {}\n]",
+            region_name(palette),
+            palette.offset,
+            describe(palette)
+        )
+        .unwrap();
+    }
+
+    for partial in &partial_palettes {
+        let base = closest(&palettes, partial.offset)
+            .map(|base| format!("    {{ region = {:#x} }},\n", base.offset))
+            .unwrap_or_default();
+        let entry = format!(
+            "[[palette]]\nname = \"{}\"\nlayout = [\n{}    {{ region = {:#x}, start = 0 }}, # {}\n]\n",
+            region_name(partial),
+            base,
+            partial.offset,
+            describe(partial)
+        );
+        write_commented(
+            &mut map,
+            &format!(
+                "{} palette(s), needs a full set of palettes under it and where it starts",
+                partial.data.len() / 32
+            ),
+            &entry,
+        );
+    }
+
+    for tileset in &tilesets {
+        writeln!(
+            map,
+            "\n[[tileset]]\nname = \"{}\"\nlayout = [\n    {{ region = {:#x}, offset = 0 }}, # {}\n]",
+            region_name(tileset),
+            tileset.offset,
+            describe(tileset)
+        )
+        .unwrap();
+    }
+
+    for sprite in &sprites {
+        match sprite {
+            Ok(entry) => {
+                map.push('\n');
+                map.push_str(entry);
+            }
+            Err((entry, reason)) => write_commented(&mut map, reason, entry),
+        }
+    }
+
+    map
+}
+
+/// The `[[sprite]]` entry for a tile map, or the entry with its missing references left empty
+/// and the reason it can't be used as is.
+fn draft_sprite(
+    tilemap: &ScanResult,
+    tilesets: &[&ScanResult],
+    palettes: &[&ScanResult],
+) -> Result<String, (String, String)> {
+    let max_tile = tilemap
+        .data
+        .chunks_exact(2)
+        .map(|entry| (u16::from_le_bytes([entry[0], entry[1]]) & 0x3ff) as usize)
+        .max()
+        .unwrap_or(0);
+
+    let tileset = tilesets
+        .iter()
+        .filter(|tileset| tileset.data.len() / 32 > max_tile)
+        .min_by_key(|tileset| (tileset.data.len(), tileset.offset.abs_diff(tilemap.offset)));
+    let palette = closest(palettes, tilemap.offset);
+
+    let (width, height) = guess_size(tilemap.data.len() / 2);
+    let entry = format!(
+        "[[sprite]]\nname = \"{}\"\nsize = [{}, {}]\ntileset = \"{}\"\npalette = \"{}\"\nlayout-region = {:#x} # {}\n",
+        region_name(tilemap),
+        width,
+        height,
+        tileset.map(|tileset| region_name(tileset)).unwrap_or_default(),
+        palette.map(region_name).unwrap_or_default(),
+        tilemap.offset,
+        describe(tilemap)
+    );
+
+    match (tileset, palette) {
+        (Some(_), Some(_)) => Ok(entry),
+        (None, _) => Err((entry, format!("no tile set has tile {:#x}", max_tile))),
+        (_, None) => Err((entry, "no palette found".to_string())),
+    }
+}
+
+/// Leave an entry in as a comment to be filled in by hand.
+fn write_commented(map: &mut String, reason: &str, entry: &str) {
+    writeln!(map, "\n# {}", reason).unwrap();
+    for line in entry.lines() {
+        writeln!(map, "# {}", line).unwrap();
+    }
+}
+
+fn closest<'a>(results: &[&'a ScanResult], offset: usize) -> Option<&'a ScanResult> {
+    results
+        .iter()
+        .copied()
+        .min_by_key(|result| result.offset.abs_diff(offset))
+}
+
+fn region_name(result: &ScanResult) -> String {
+    let kind = match result.kind {
+        Some(RegionKind::TileMap) => "sprite".to_string(),
+        Some(kind) => kind.to_string(),
+        None => "unknown".to_string(),
+    };
+
+    format!("{}-{:x}", kind, result.offset)
+}
+
+fn describe(result: &ScanResult) -> String {
+    format!(
+        "{} bytes, score {:.2}",
+        result.data.len(),
+        result.score.value()
+    )
+}
+
+/// Background layers are 32 tiles wide, anything else is assumed to be about square and wider
+/// than it is tall.
+fn guess_size(entries: usize) -> (usize, usize) {
+    if entries.is_multiple_of(32) {
+        return (32, entries / 32);
+    }
+
+    let height = (1..=entries.isqrt())
+        .rev()
+        .find(|height| entries.is_multiple_of(*height))
+        .unwrap_or(1);
+
+    (entries / height, height)
+}
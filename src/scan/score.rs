@@ -0,0 +1,107 @@
+//! How much a scanned stream looks like real data rather than something that happened to decode.
+//!
+//! Every signal is turned into a value between 0 (garbage) and 1 (real data), the score is their
+//! average. Random bytes mostly decode as `CopySimple` with no compression to speak of and
+//! produce tiles with noisy bit planes, real graphics compress well and repeat themselves.
+
+use crate::{CompressionStats, RegionKind};
+use serde::Serialize;
+use std::collections::HashSet;
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+pub struct Score {
+    /// compressed size relative to the decompressed size
+    pub ratio: f64,
+    /// fraction of the output copied from the stream as-is, not used for palettes which hardly
+    /// compress at all
+    pub literal: Option<f64>,
+    /// entropy of the bit planes of tile data, from 0 to 1
+    pub entropy: Option<f64>,
+    /// fraction of tiles that are blank or repeat an earlier tile
+    pub redundant_tiles: Option<f64>,
+}
+
+impl Score {
+    pub fn new(data: &[u8], kind: Option<RegionKind>, stats: &CompressionStats) -> Self {
+        let literal = (kind != Some(RegionKind::Palette) && !data.is_empty())
+            .then(|| stats.literal_bytes as f64 / data.len() as f64);
+
+        let tiles = (kind == Some(RegionKind::TileSet)).then(|| data.chunks_exact(32));
+
+        Self {
+            ratio: stats.ratio(),
+            literal,
+            entropy: tiles.clone().map(bit_plane_entropy),
+            redundant_tiles: tiles.map(|tiles| {
+                let count = tiles.len();
+                let mut seen = HashSet::new();
+                let redundant = tiles
+                    .filter(|&tile| tile.iter().all(|&value| value == 0) || !seen.insert(tile))
+                    .count();
+
+                redundant as f64 / count as f64
+            }),
+        }
+    }
+
+    /// The combined score, from 0 for garbage to 1 for data that is very likely real.
+    pub fn value(&self) -> f64 {
+        let signals = [
+            // anything compressed to half its size is fine, but streams that are almost nothing
+            // but runs are more likely to come from a few random bytes
+            Some(if self.ratio < 0.15 {
+                self.ratio / 0.15
+            } else {
+                ((1.0 - self.ratio) * 2.0).clamp(0.0, 1.0)
+            }),
+            self.literal.map(|literal| 1.0 - literal),
+            self.entropy.map(|entropy| 1.0 - entropy),
+            // a few blank or repeated tiles are normal, a set made of nothing else isn't
+            self.redundant_tiles
+                .map(|redundant| ((1.0 - redundant) * 2.0).clamp(0.0, 1.0)),
+        ];
+
+        let (sum, count) = signals
+            .iter()
+            .flatten()
+            .fold((0.0, 0), |(sum, count), value| (sum + value, count + 1));
+        sum / count as f64
+    }
+}
+
+/// Average entropy of the bytes of each of the four bit planes of 4bpp tiles, relative to the
+/// most they could have with the number of bytes available.
+fn bit_plane_entropy<'a>(tiles: impl Iterator<Item = &'a [u8]>) -> f64 {
+    let mut histograms = [[0usize; 256]; 4];
+    let mut count = 0;
+
+    for tile in tiles {
+        // planes 0 and 1 are interleaved in the first 16 bytes, planes 2 and 3 in the others
+        for (i, &value) in tile.iter().enumerate() {
+            let plane = (i / 16) * 2 + i % 2;
+            histograms[plane][value as usize] += 1;
+        }
+        count += 8;
+    }
+
+    if count < 2 {
+        return 0.0;
+    }
+
+    let max = (count.min(256) as f64).log2();
+    let total = histograms
+        .iter()
+        .map(|histogram| {
+            histogram
+                .iter()
+                .filter(|&&n| n > 0)
+                .map(|&n| {
+                    let p = n as f64 / count as f64;
+                    -p * p.log2()
+                })
+                .sum::<f64>()
+        })
+        .sum::<f64>();
+
+    total / 4.0 / max
+}
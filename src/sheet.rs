@@ -0,0 +1,154 @@
+//! Rendering a whole tile set into a single image.
+
+use crate::{Palette, Tile};
+use image::{GenericImage, Rgba, RgbaImage};
+
+const GRID_COLOR: Rgba<u8> = Rgba([255, 0, 255, 255]);
+
+/// 3x5 pixel glyphs for the hex digits, one row per byte with the leftmost pixel in bit 2
+const GLYPHS: [[u8; 5]; 16] = [
+    [0b111, 0b101, 0b101, 0b101, 0b111],
+    [0b010, 0b110, 0b010, 0b010, 0b111],
+    [0b111, 0b001, 0b111, 0b100, 0b111],
+    [0b111, 0b001, 0b111, 0b001, 0b111],
+    [0b101, 0b101, 0b111, 0b001, 0b001],
+    [0b111, 0b100, 0b111, 0b001, 0b111],
+    [0b111, 0b100, 0b111, 0b101, 0b111],
+    [0b111, 0b001, 0b001, 0b001, 0b001],
+    [0b111, 0b101, 0b111, 0b101, 0b111],
+    [0b111, 0b101, 0b111, 0b001, 0b111],
+    [0b010, 0b101, 0b111, 0b101, 0b101],
+    [0b110, 0b101, 0b110, 0b101, 0b110],
+    [0b011, 0b100, 0b100, 0b100, 0b011],
+    [0b110, 0b101, 0b101, 0b101, 0b110],
+    [0b111, 0b100, 0b111, 0b100, 0b111],
+    [0b111, 0b100, 0b111, 0b100, 0b100],
+];
+
+/// How tiles are laid out on a sheet.
+#[derive(Debug, Clone)]
+pub struct TileSheet {
+    /// tiles per row
+    pub columns: u32,
+    /// every pixel of a tile becomes a square this large
+    pub scale: u32,
+    /// separate the tiles by one pixel wide lines
+    pub grid: bool,
+    /// print the hex index of every tile into its top left corner
+    pub labels: bool,
+}
+
+impl Default for TileSheet {
+    fn default() -> Self {
+        Self {
+            columns: 16,
+            scale: 4,
+            grid: false,
+            labels: false,
+        }
+    }
+}
+
+impl TileSheet {
+    fn cell_size(&self) -> u32 {
+        8 * self.scale
+    }
+
+    fn gap(&self) -> u32 {
+        self.grid as u32
+    }
+
+    /// Size of the sheet for `tiles` tiles.
+    pub fn dimensions(&self, tiles: usize) -> (u32, u32) {
+        let columns = self.columns.min(tiles as u32).max(1);
+        let rows = (tiles as u32).div_ceil(self.columns).max(1);
+        let step = self.cell_size() + self.gap();
+
+        (columns * step + self.gap(), rows * step + self.gap())
+    }
+
+    /// Position of the top left pixel of tile `index`.
+    pub fn tile_position(&self, index: usize) -> (u32, u32) {
+        let step = self.cell_size() + self.gap();
+        let index = index as u32;
+
+        (
+            (index % self.columns) * step + self.gap(),
+            (index / self.columns) * step + self.gap(),
+        )
+    }
+
+    pub fn render(&self, tiles: &[Tile], palette: &Palette) -> RgbaImage {
+        let (width, height) = self.dimensions(tiles.len());
+        let mut image = RgbaImage::new(width, height);
+        if self.grid {
+            let step = self.cell_size() + 1;
+            for (x, y, pixel) in image.enumerate_pixels_mut() {
+                if x % step == 0 || y % step == 0 {
+                    *pixel = GRID_COLOR;
+                }
+            }
+        }
+
+        for (i, tile) in tiles.iter().enumerate() {
+            let tile_image = tile.with_palette(palette, Default::default());
+            let tile_image = image::imageops::resize(
+                &tile_image,
+                self.cell_size(),
+                self.cell_size(),
+                image::imageops::FilterType::Nearest,
+            );
+
+            let (x, y) = self.tile_position(i);
+            image
+                .copy_from(&tile_image, x, y)
+                .expect("tile lies outside of the sheet");
+
+            if self.labels {
+                self.draw_label(&mut image, x, y, i);
+            }
+        }
+
+        image
+    }
+
+    /// White digits on a black box, scaled along with the tiles but at most half as large.
+    fn draw_label(&self, image: &mut RgbaImage, x: u32, y: u32, index: usize) {
+        let pixel = (self.scale / 2).max(1);
+        let digits = format!("{:x}", index);
+        let width = (digits.len() as u32 * 4 + 1) * pixel;
+        let height = 7 * pixel;
+
+        let cell = self.cell_size();
+        for dy in 0..height.min(cell) {
+            for dx in 0..width.min(cell) {
+                image.put_pixel(x + dx, y + dy, Rgba([0, 0, 0, 255]));
+            }
+        }
+
+        for (n, digit) in digits.bytes().enumerate() {
+            let glyph = GLYPHS[(digit as char).to_digit(16).unwrap() as usize];
+            for (row, bits) in glyph.iter().enumerate() {
+                for col in 0..3 {
+                    if bits >> (2 - col) & 1 == 0 {
+                        continue;
+                    }
+
+                    let left = (1 + n as u32 * 4 + col) * pixel;
+                    let top = (1 + row as u32) * pixel;
+                    for dy in 0..pixel {
+                        for dx in 0..pixel {
+                            if left + dx < cell && top + dy < cell {
+                                image.put_pixel(
+                                    x + left + dx,
+                                    y + top + dy,
+                                    Rgba([255, 255, 255, 255]),
+                                );
+                            }
+                        }
+                    }
+                }
+            }
+        }
+    }
+}
@@ -1,6 +1,7 @@
 use crate::{PaletteCollection, TileMap, TileSet};
+use alloc::sync::Arc;
+#[cfg(feature = "std")]
 use image::{GenericImage, RgbaImage};
-use std::sync::Arc;
 
 #[derive(Debug, Clone)]
 pub struct Sprite {
@@ -31,6 +32,7 @@ impl Sprite {
         }
     }
 
+    #[cfg(feature = "std")]
     pub fn to_image(&self) -> RgbaImage {
         let mut image = RgbaImage::new(self.size.0 * 8, self.size.1 * 8);
 
@@ -1,6 +1,9 @@
 use crate::{palette::ColorIndex, Compressable, DecompressError, PaletteIndex};
 
+#[cfg(feature = "std")]
 use super::Palette;
+use alloc::{boxed::Box, vec::Vec};
+#[cfg(feature = "std")]
 use image::{Rgba, RgbaImage};
 
 //pdp uses 4bpp tiles
@@ -48,21 +51,35 @@ impl TileSet {
     }
 }
 
+impl Default for TileSet {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 impl PartialTileSet {
     pub fn tiles(&self) -> &[Tile] {
         &self.0
     }
 
+    pub fn tiles_mut(&mut self) -> &mut [Tile] {
+        &mut self.0
+    }
+
     pub fn len(&self) -> usize {
         self.0.len()
     }
+
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
 }
 
 impl Compressable for PartialTileSet {
     fn try_from_slice(data: &[u8]) -> Result<Self, DecompressError> {
-        if data.len() % 32 != 0 {
-            return Err(DecompressError::InvalidLayout(
-                "Tile data must be a multiple of 32 bytes".to_string(),
+        if !data.len().is_multiple_of(32) {
+            return Err(DecompressError::invalid_layout(
+                "Tile data must be a multiple of 32 bytes",
             ));
         }
 
@@ -73,9 +90,13 @@ impl Compressable for PartialTileSet {
 
         Ok(PartialTileSet(tiles))
     }
+
+    fn to_bytes(&self) -> Vec<u8> {
+        self.0.iter().flat_map(Tile::to_bytes).collect()
+    }
 }
 
-impl std::ops::Index<usize> for TileSet {
+impl core::ops::Index<usize> for TileSet {
     type Output = Tile;
 
     fn index(&self, index: usize) -> &Self::Output {
@@ -87,9 +108,13 @@ impl TileMap {
     pub fn len(&self) -> usize {
         self.0.len()
     }
+
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
 }
 
-impl std::ops::Index<usize> for TileMap {
+impl core::ops::Index<usize> for TileMap {
     type Output = TileMapEntry;
 
     fn index(&self, index: usize) -> &Self::Output {
@@ -97,11 +122,17 @@ impl std::ops::Index<usize> for TileMap {
     }
 }
 
+impl core::ops::IndexMut<usize> for TileMap {
+    fn index_mut(&mut self, index: usize) -> &mut Self::Output {
+        &mut self.0[index]
+    }
+}
+
 impl Compressable for TileMap {
     fn try_from_slice(data: &[u8]) -> Result<Self, DecompressError> {
-        if data.len() % 2 != 0 {
-            return Err(DecompressError::InvalidLayout(
-                "TileMap data must be a multiple of 2 bytes".to_string(),
+        if !data.len().is_multiple_of(2) {
+            return Err(DecompressError::invalid_layout(
+                "TileMap data must be a multiple of 2 bytes",
             ));
         }
 
@@ -115,9 +146,20 @@ impl Compressable for TileMap {
 
         Ok(TileMap(tile_map))
     }
+
+    fn to_bytes(&self) -> Vec<u8> {
+        self.0
+            .iter()
+            .flat_map(|entry| entry.0.to_le_bytes())
+            .collect()
+    }
 }
 
 impl TileMapEntry {
+    pub fn new(value: u16) -> Self {
+        TileMapEntry(value)
+    }
+
     pub fn tile_index(&self) -> usize {
         (self.0 & 0x3FF) as usize
     }
@@ -148,6 +190,10 @@ impl Tile {
         &self.0
     }
 
+    pub fn data_mut(&mut self) -> &mut [ColorIndex] {
+        &mut self.0
+    }
+
     pub fn from_slice(data: &[u8]) -> Self {
         assert!(data.len() == 32, "Tile data must be 32 bytes long");
 
@@ -165,9 +211,9 @@ impl Tile {
                 let shift = 7 - col;
                 let mut color = 0;
 
-                for plane in 0..PLANE_CNT {
-                    let bit = (row_planes[plane] >> shift) & 1;
-                    color |= (bit << plane) as u8;
+                for (plane, row_plane) in row_planes.iter().enumerate() {
+                    let bit = (row_plane >> shift) & 1;
+                    color |= bit << plane;
                 }
 
                 tile[row * 8 + col] = ColorIndex::new(color as usize);
@@ -177,6 +223,25 @@ impl Tile {
         Tile(tile)
     }
 
+    /// The tile in the format [`Tile::from_slice`] reads.
+    pub fn to_bytes(&self) -> [u8; 32] {
+        let mut data = [0; 32];
+
+        for row in 0..8 {
+            for col in 0..8 {
+                let color = self.0[row * 8 + col].index();
+                for plane in 0..PLANE_CNT {
+                    if (color >> plane) & 1 != 0 {
+                        data[16 * (plane / 2) + row * 2 + plane % 2] |= 1 << (7 - col);
+                    }
+                }
+            }
+        }
+
+        data
+    }
+
+    #[cfg(feature = "std")]
     pub fn with_palette(&self, palette: &Palette, settings: TileSettings) -> RgbaImage {
         RgbaImage::from_fn(8, 8, |x, y| {
             let x = if settings.x_flip { 7 - x } else { x };
@@ -189,7 +254,8 @@ impl Tile {
                 Rgba([0, 0, 0, 0])
             } else {
                 let color = palette[color_index];
-                Rgba([color[0], color[1], color[2], 255])
+                let [r, g, b] = color.0;
+                Rgba([r, g, b, 255])
             }
         })
     }
@@ -0,0 +1,15 @@
+//! Fixtures shared by the tests.
+
+/// Bytes that don't compress, from a simple xorshift so the tests don't depend on the rng of the
+/// day.
+pub fn noise(len: usize) -> Vec<u8> {
+    let mut state = 0x2545f491u32;
+    (0..len)
+        .map(|_| {
+            state ^= state << 13;
+            state ^= state >> 17;
+            state ^= state << 5;
+            state as u8
+        })
+        .collect()
+}
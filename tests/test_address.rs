@@ -0,0 +1,97 @@
+#![cfg(feature = "std")]
+
+use thanatos::{MapMode, RomMap, SnesAddress};
+
+#[test]
+fn test_parse_address() {
+    assert_eq!("$91:DDBB".parse(), Ok(SnesAddress::new(0x91, 0xddbb)));
+    assert_eq!("80:a116".parse(), Ok(SnesAddress::new(0x80, 0xa116)));
+    assert_eq!("$C0:0".parse(), Ok(SnesAddress::new(0xc0, 0)));
+
+    for invalid in [
+        "91DDBB",
+        "$:DDBB",
+        "$91:",
+        "$100:0000",
+        "$91:1DDBB",
+        "$91:XYZ",
+    ] {
+        assert!(invalid.parse::<SnesAddress>().is_err(), "{}", invalid);
+    }
+
+    let address = SnesAddress::new(0x91, 0xddbb);
+    assert_eq!(address.to_string().parse(), Ok(address));
+}
+
+#[test]
+fn test_mapping() {
+    let lorom = MapMode::LoRom;
+    assert_eq!(lorom.offset(SnesAddress::new(0x91, 0xddbb)), Some(0x8ddbb));
+    assert_eq!(lorom.offset(SnesAddress::new(0x11, 0xddbb)), Some(0x8ddbb));
+    assert_eq!(lorom.offset(SnesAddress::new(0x91, 0x1234)), None);
+    assert_eq!(lorom.address(0x8ddbb), SnesAddress::new(0x11, 0xddbb));
+
+    let hirom = MapMode::HiRom;
+    assert_eq!(hirom.offset(SnesAddress::new(0xc8, 0x1234)), Some(0x81234));
+    assert_eq!(hirom.offset(SnesAddress::new(0x48, 0x1234)), Some(0x81234));
+    assert_eq!(hirom.offset(SnesAddress::new(0x88, 0x9234)), Some(0x89234));
+    assert_eq!(hirom.offset(SnesAddress::new(0x08, 0x1234)), None);
+    assert_eq!(hirom.offset(SnesAddress::new(0x7e, 0x1234)), None);
+    assert_eq!(hirom.address(0x81234), SnesAddress::new(0xc8, 0x1234));
+
+    let exhirom = MapMode::ExHiRom;
+    assert_eq!(
+        exhirom.offset(SnesAddress::new(0xc8, 0x1234)),
+        Some(0x81234)
+    );
+    assert_eq!(
+        exhirom.offset(SnesAddress::new(0x48, 0x1234)),
+        Some(0x481234)
+    );
+    assert_eq!(exhirom.address(0x481234), SnesAddress::new(0x48, 0x1234));
+
+    for mapping in [lorom, hirom, exhirom] {
+        for offset in [0, 0x7fff, 0x8000, 0x12345, 0x3fffff] {
+            assert_eq!(mapping.offset(mapping.address(offset)), Some(offset));
+        }
+    }
+}
+
+#[test]
+fn test_map_addresses() -> anyhow::Result<()> {
+    let src = r#"
+        supported_roms = []
+
+        [[palette]]
+        name = "base"
+        layout = [{ region = "$8C:A95D" }, { region = "0x8dd27", start = 5 }]
+
+        [[tileset]]
+        name = "tiles"
+        layout = [{ region = 0x8a915, offset = 0 }]
+
+        [[sprite]]
+        name = "sprite"
+        size = [32, 32]
+        tileset = "tiles"
+        palette = "base"
+        layout-region = "$91:DDBB"
+    "#;
+
+    let map = RomMap::parse(src)?;
+    assert_eq!(map.mapping, MapMode::LoRom);
+    assert_eq!(map.palettes[0].layout[0].region, 0x6295d);
+    assert_eq!(map.palettes[0].layout[1].region, 0x8dd27);
+    assert_eq!(map.tilesets[0].layout[0].region, 0x8a915);
+    assert_eq!(map.sprites[0].layout_region, 0x8ddbb);
+    assert_eq!(map.address(0x8ddbb).to_string(), "$11:DDBB");
+
+    let hirom = RomMap::parse(&format!("mapping = \"hirom\"\n{}", src))?;
+    assert_eq!(hirom.sprites[0].layout_region, 0x11ddbb);
+
+    // not in ROM
+    assert!(RomMap::parse(&src.replace("$91:DDBB", "$91:1234")).is_err());
+    assert!(RomMap::parse(&src.replace("$91:DDBB", "nonsense")).is_err());
+
+    Ok(())
+}
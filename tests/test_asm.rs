@@ -0,0 +1,78 @@
+use std::fs;
+use thanatos::{assemble, disassemble, Compressor, Decompressor};
+
+#[test]
+fn test_assemble() -> anyhow::Result<()> {
+    let src = r#"
+        ; every operation once
+        copy 4 "00 11 22 33"
+        nibble 5 upper fixed=0x3 "12345"
+        nibble 3 lower fixed=0xf initial=0x7 "abc"
+        double 2 "aa bb"
+        interleave 2 fixed=0x20 second "01 02"
+        backread count=4 back=4
+        backread count=2 back=0x10 long
+        repeat 5 0xee
+        repeat 3 0x00 long
+        copy 2 "ff fe" ; re-read by the backref below
+        backref count=3 back=5
+        exit
+    "#;
+    let compressed = assemble(src)?;
+    let result = Decompressor::new(&compressed, 0).decompress()?;
+
+    let expected = [
+        vec![0x00, 0x11, 0x22, 0x33],
+        vec![0x31, 0x32, 0x33, 0x34, 0x35],
+        vec![0x7f, 0xaf, 0xbf, 0xcf],
+        vec![0xaa, 0xaa, 0xbb, 0xbb],
+        vec![0x01, 0x20, 0x02, 0x20],
+        vec![0x01, 0x20, 0x02, 0x20],
+        vec![0x7f, 0xaf],
+        vec![0xee; 5],
+        vec![0x00; 3],
+        vec![0xff, 0xfe],
+        vec![0xff, 0xfe],
+    ]
+    .concat();
+    assert_eq!(result.data, expected);
+    assert_eq!(result.bytes_read, compressed.len());
+
+    Ok(())
+}
+
+#[test]
+fn test_assemble_errors() {
+    for (src, line) in [
+        ("copy 3 \"0011\"", 1),
+        ("exit\ncopy 65 \"00\"", 2),
+        ("\n\nnibble 2 upper \"12\"", 3),
+        ("nibble 2 left fixed=0 \"12\"", 1),
+        ("nibble 2 upper fixed=3 initial=1 \"12\"", 1),
+        ("repeat 2 0x00", 1),
+        ("backref count=3 back=2 long", 1),
+        ("bytes \"0\"", 1),
+        ("exit now", 1),
+        ("jump 3", 1),
+    ] {
+        let error = assemble(src).expect_err(src);
+        assert_eq!(error.line, line, "{}", src);
+    }
+}
+
+#[test]
+fn test_disassemble_roundtrip() -> anyhow::Result<()> {
+    for entry in fs::read_dir("tests/decompress_data")? {
+        let data = fs::read(entry?.path())?;
+        let compressed = Compressor::new(&data).compress();
+
+        let (trace, result) = Decompressor::new(&compressed, 0).decompress_traced();
+        assert_eq!(result?.data, data);
+
+        let text = disassemble(&trace);
+        assert!(!text.contains("bytes"), "fell back to raw bytes:\n{}", text);
+        assert_eq!(assemble(&text)?, compressed);
+    }
+
+    Ok(())
+}
@@ -0,0 +1,98 @@
+mod common;
+
+use std::fs;
+use thanatos::{
+    assemble, ChainEnd, ChainTable, Compressor, DecompressOptions, Decompressor, LoopPolicy,
+};
+
+/// every offset the table rules out has to fail to decompress
+fn check_table(src: &[u8], options: &DecompressOptions) -> usize {
+    let chains = ChainTable::new(src);
+    let mut candidates = 0;
+
+    for offset in 0..src.len() {
+        if chains.may_decompress(offset, options) {
+            candidates += 1;
+            continue;
+        }
+
+        let result = Decompressor::with_options(src, offset, options.clone()).decompress();
+        assert!(
+            result.is_err(),
+            "{:#x} was ruled out but decompressed",
+            offset
+        );
+    }
+
+    candidates
+}
+
+#[test]
+fn test_chain_end() -> anyhow::Result<()> {
+    let src = r#"
+        copy 2 "00 11"
+        repeat 3 0x22
+        backref count=3 back=5
+        exit
+    "#;
+    let compressed = assemble(src)?;
+    let chains = ChainTable::new(&compressed);
+
+    assert_eq!(chains.end(0), ChainEnd::Backref { at: 5, to: 2 });
+    assert_eq!(chains.end(3), ChainEnd::Backref { at: 5, to: 2 });
+    assert_eq!(chains.end(7), ChainEnd::Exit(7));
+    assert_eq!(chains.end(compressed.len()), ChainEnd::Invalid);
+
+    // the backref would reach before the start
+    assert!(chains.may_decompress(0, &DecompressOptions::default()));
+    assert!(!chains.may_decompress(3, &DecompressOptions::default()));
+    assert!(chains.may_decompress(3, &DecompressOptions::permissive()));
+
+    Ok(())
+}
+
+#[test]
+fn test_chain_backread() -> anyhow::Result<()> {
+    let compressed = assemble("copy 2 \"00 11\"\nbackread count=2 back=2\nexit")?;
+    let chains = ChainTable::new(&compressed);
+
+    assert!(chains.may_decompress(0, &DecompressOptions::default()));
+    // starting at the backread leaves nothing to read back from
+    assert!(!chains.may_decompress(3, &DecompressOptions::default()));
+
+    Ok(())
+}
+
+#[test]
+fn test_chain_compressed() -> anyhow::Result<()> {
+    for entry in fs::read_dir("tests/decompress_data")? {
+        let data = fs::read(entry?.path())?;
+        let compressed = Compressor::new(&data).compress();
+
+        let chains = ChainTable::new(&compressed);
+        assert!(chains.may_decompress(0, &DecompressOptions::permissive()));
+    }
+
+    Ok(())
+}
+
+#[test]
+fn test_chain_noise() {
+    let noise = common::noise(0x4000);
+
+    let limited = DecompressOptions {
+        max_output: 0x400,
+        max_input_span: Some(0x40),
+        loop_policy: LoopPolicy::Off,
+        allow_backref_before_start: false,
+    };
+    for options in [
+        DecompressOptions::default(),
+        DecompressOptions::strict(),
+        DecompressOptions::permissive(),
+        limited,
+    ] {
+        let candidates = check_table(&noise, &options);
+        assert!(candidates < noise.len() / 2, "{:?}", options);
+    }
+}
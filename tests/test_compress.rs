@@ -0,0 +1,107 @@
+mod common;
+
+use std::fs;
+use thanatos::{CompressionMode, Compressor, Decompressor};
+
+fn roundtrip(data: &[u8]) -> anyhow::Result<Vec<u8>> {
+    let mut optimal = Vec::new();
+    for mode in [CompressionMode::Optimal, CompressionMode::Greedy] {
+        let compressed = Compressor::with_mode(data, mode).compress();
+        let result = Decompressor::new(&compressed, 0).decompress()?;
+
+        assert_eq!(
+            result.data, data,
+            "round trip changed the data ({:?})",
+            mode
+        );
+        assert_eq!(result.bytes_read, compressed.len());
+
+        if mode == CompressionMode::Optimal {
+            optimal = compressed;
+        }
+    }
+
+    Ok(optimal)
+}
+
+#[test]
+fn test_compress_roundtrip() -> anyhow::Result<()> {
+    for entry in fs::read_dir("tests/decompress_data")? {
+        let entry = entry?;
+        let data = fs::read(entry.path())?;
+
+        let compressed = roundtrip(&data)?;
+        println!(
+            "{}: {} -> {} bytes",
+            entry.file_name().to_string_lossy(),
+            data.len(),
+            compressed.len()
+        );
+    }
+
+    Ok(())
+}
+
+#[test]
+fn test_compress_synthetic() -> anyhow::Result<()> {
+    let random = common::noise(0x1440);
+
+    let noise = random[..0x800].to_vec();
+    let nibbles = random[0x800..0xc00]
+        .iter()
+        .map(|&byte| 0xf0 | (byte & 0x0f))
+        .collect::<Vec<_>>();
+    let interleaved = random[0xc00..0x1000]
+        .iter()
+        .flat_map(|&byte| [byte, 0x20])
+        .collect::<Vec<_>>();
+    let doubled = random[0x1000..0x1400]
+        .iter()
+        .flat_map(|&byte| [byte; 2])
+        .collect::<Vec<_>>();
+    let runs = random[0x1400..]
+        .iter()
+        .enumerate()
+        .flat_map(|(i, &byte)| vec![byte; 1 + i * 17])
+        .collect::<Vec<_>>();
+    let repeated = noise[..0x20].repeat(0x40);
+
+    roundtrip(&[])?;
+    roundtrip(&[0x42])?;
+    roundtrip(&noise)?;
+    roundtrip(&nibbles)?;
+    roundtrip(&interleaved)?;
+    roundtrip(&doubled)?;
+    roundtrip(&runs)?;
+    roundtrip(&repeated)?;
+    roundtrip(&[noise, nibbles, interleaved, doubled, runs, repeated].concat())?;
+
+    Ok(())
+}
+
+/// The streams in the game come back byte for byte. The decompressed data is named after the
+/// offset of its stream.
+#[test]
+#[ignore = "needs panepon.sfc"]
+fn test_compress_original() -> anyhow::Result<()> {
+    let rom = fs::read("panepon.sfc")?;
+
+    for entry in fs::read_dir("tests/decompress_data")? {
+        let entry = entry?;
+        let name = entry.file_name().into_string().unwrap();
+        let (_, offset) = name.split_once('_').unwrap();
+        let offset = usize::from_str_radix(offset, 16)?;
+
+        let data = fs::read(entry.path())?;
+        let original = Decompressor::new(&rom, offset).decompress()?;
+        let encoded = Compressor::with_mode(&data, CompressionMode::Greedy).compress();
+        assert_eq!(
+            encoded,
+            rom[offset..offset + original.bytes_read],
+            "{} doesn't re-encode identically",
+            name
+        );
+    }
+
+    Ok(())
+}
@@ -0,0 +1,32 @@
+use std::fs;
+use thanatos::Decompressor;
+
+/// Inputs that used to crash or hang the decompressor. Every offset is decoded, including ones
+/// past the end of the data, and only has to return instead of panicking.
+#[test]
+fn test_decompress_corpus() -> anyhow::Result<()> {
+    for entry in fs::read_dir("tests/decompress_corpus")? {
+        let data = fs::read(entry?.path())?;
+
+        for offset in 0..=data.len() + 1 {
+            let _ = Decompressor::new(&data, offset).decompress();
+            let _ = Decompressor::new(&data, offset).decompress_traced();
+        }
+    }
+
+    Ok(())
+}
+
+#[test]
+fn test_stream_at_end_of_data() -> anyhow::Result<()> {
+    // the last byte of the data can be read, but nothing after it
+    let result = Decompressor::new(&[0x01, 0x12, 0x34, 0xff], 0).decompress()?;
+    assert_eq!(result.data, [0x12, 0x34]);
+    assert_eq!(result.bytes_read, 4);
+
+    assert!(Decompressor::new(&[0x01, 0x12, 0x34], 0)
+        .decompress()
+        .is_err());
+
+    Ok(())
+}
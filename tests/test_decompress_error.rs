@@ -0,0 +1,96 @@
+use thanatos::{assemble, DecompressError, Decompressor, Operation};
+
+#[test]
+fn test_error_context() -> anyhow::Result<()> {
+    let mut data = vec![0xaa; 0x10];
+    data.extend(assemble(
+        r#"
+        copy 2 "11 22"
+        backread count=4 back=2
+        backread count=4 back=0x20
+        exit
+        "#,
+    )?);
+
+    let error = Decompressor::new(&data, 0x10).decompress().unwrap_err();
+    assert!(matches!(error, DecompressError::InvalidOperation(_)));
+
+    let context = error.context();
+    assert_eq!(context.region_start, 0x10);
+    assert_eq!(context.read_index, 0x10 + 7);
+    assert_eq!(context.opcode, Some(0x88));
+    assert_eq!(
+        context.operation,
+        Some(Operation::CopyBackread {
+            count: 3,
+            back: 0x20
+        })
+    );
+    assert_eq!(context.bytes_produced, 6);
+
+    // truncated in the middle of an operation
+    let error = Decompressor::new(&data[..0x12], 0x10)
+        .decompress()
+        .unwrap_err();
+    assert!(matches!(error, DecompressError::InvalidData(_)));
+    assert_eq!(error.context().opcode, Some(0x01));
+    assert_eq!(error.context().bytes_produced, 1);
+
+    // the summary leaves out where it happened, so errors can be grouped by it
+    let other = Decompressor::new(&data[..0x13], 0x11)
+        .decompress()
+        .unwrap_err();
+    assert_ne!(error.to_string(), other.to_string());
+    assert_eq!(error.summary(), other.summary());
+    assert!(error.to_string().starts_with(error.summary()));
+
+    Ok(())
+}
+
+#[test]
+#[cfg(feature = "std")]
+fn test_rom_error_names_definition() -> anyhow::Result<()> {
+    use thanatos::{MappedRom, RegionKind, Rom, RomError, RomMap, SnesAddress};
+
+    let mut data = assemble("repeat 0x200 0x00\nexit")?;
+    let tiles_offset = data.len();
+    data.extend(assemble("copy 3 \"01 02 03\"\nexit")?);
+
+    let map = RomMap::parse(&format!(
+        r#"
+        supported_roms = []
+        sprite = []
+
+        [[palette]]
+        name = "base"
+        layout = [{{ region = 0 }}]
+
+        [[tileset]]
+        name = "broken-tiles"
+        layout = [{{ region = {}, offset = 0 }}]
+        "#,
+        tiles_offset
+    ))?;
+
+    let error = MappedRom::new_forced(&Rom::new(&data), &map).unwrap_err();
+    let RomError::Decompress {
+        kind,
+        name,
+        offset,
+        address,
+        source,
+    } = &error
+    else {
+        panic!("unexpected error: {}", error);
+    };
+    assert_eq!(*kind, RegionKind::TileSet);
+    assert_eq!(name, "broken-tiles");
+    assert_eq!(*offset, tiles_offset);
+    assert_eq!(*address, SnesAddress::from_lorom_offset(tiles_offset));
+    assert!(error.to_string().contains(&format!("({})", address)));
+    assert!(matches!(source, DecompressError::InvalidLayout { .. }));
+    assert_eq!(source.context().region_start, tiles_offset);
+    assert_eq!(source.context().bytes_produced, 3);
+
+    Ok(())
+}
@@ -0,0 +1,70 @@
+use std::fs;
+use thanatos::{assemble, Compressor, DecompressOptions, Decompressor};
+
+fn assert_same(src: &[u8], offset: usize, options: &DecompressOptions) {
+    let bytewise = Decompressor::with_options(src, offset, options.clone()).decompress();
+    let fast = Decompressor::with_options(src, offset, options.clone()).decompress_fast();
+
+    match (bytewise, fast) {
+        (Ok(bytewise), Ok(fast)) => {
+            assert_eq!(bytewise.data, fast.data, "at {:#x}", offset);
+            assert_eq!(bytewise.bytes_read, fast.bytes_read, "at {:#x}", offset);
+        }
+        (Err(bytewise), Err(fast)) => {
+            assert_eq!(format!("{:?}", bytewise), format!("{:?}", fast));
+        }
+        (bytewise, fast) => panic!("at {:#x}: {:?} != {:?}", offset, bytewise, fast),
+    }
+}
+
+#[test]
+fn test_fast_compressed() -> anyhow::Result<()> {
+    for entry in fs::read_dir("tests/decompress_data")? {
+        let data = fs::read(entry?.path())?;
+        let compressed = Compressor::new(&data).compress();
+
+        let result = Decompressor::new(&compressed, 0).decompress_fast()?;
+        assert_eq!(result.data, data);
+        assert_eq!(result.bytes_read, compressed.len());
+    }
+
+    Ok(())
+}
+
+#[test]
+fn test_fast_overlapping_backread() -> anyhow::Result<()> {
+    let src = r#"
+        copy 3 "01 02 03"
+        backread count=0x40 back=3
+        backread count=5 back=1
+        copy 2 "04 05"
+        backref count=3 back=7 ; the second backread and the opcode of the copy again
+        bytes "aa bb"          ; payload of the re-read copy
+        exit
+    "#;
+    let compressed = assemble(src)?;
+
+    let result = Decompressor::new(&compressed, 0).decompress_fast()?;
+    assert_eq!(result.data.len(), 3 + 0x40 + 5 + 2 + 5 + 2);
+    assert_same(&compressed, 0, &DecompressOptions::default());
+
+    Ok(())
+}
+
+#[test]
+fn test_fast_noise() {
+    let mut state = 0x2545f491u32;
+    let mut next = move || {
+        state ^= state << 13;
+        state ^= state >> 17;
+        state ^= state << 5;
+        state as u8
+    };
+    let noise = (0..0x2000).map(|_| next()).collect::<Vec<_>>();
+
+    for options in [DecompressOptions::strict(), DecompressOptions::permissive()] {
+        for offset in 0..noise.len() {
+            assert_same(&noise, offset, &options);
+        }
+    }
+}
@@ -0,0 +1,112 @@
+use thanatos::{
+    assemble, Compressable, DecompressError, DecompressOptions, Decompressor, LoopPolicy,
+    PaletteCollection,
+};
+
+fn decompress(
+    data: &[u8],
+    offset: usize,
+    options: DecompressOptions,
+) -> Result<Vec<u8>, DecompressError> {
+    Decompressor::with_options(data, offset, options)
+        .decompress()
+        .map(|result| result.data)
+}
+
+#[test]
+fn test_max_output() -> anyhow::Result<()> {
+    let data = assemble("repeat 0x1000 0x00\nrepeat 0x1000 0x00\nexit")?;
+
+    let options = DecompressOptions {
+        max_output: 0x1000,
+        ..Default::default()
+    };
+    assert!(matches!(
+        decompress(&data, 0, options),
+        Err(DecompressError::MaxSizeExceeded(_))
+    ));
+    assert_eq!(decompress(&data, 0, Default::default())?.len(), 0x2000);
+
+    Ok(())
+}
+
+#[test]
+fn test_max_input_span() -> anyhow::Result<()> {
+    let data = assemble("copy 8 \"0011223344556677\"\nexit")?;
+
+    let options = DecompressOptions {
+        max_input_span: Some(8),
+        ..Default::default()
+    };
+    let error = decompress(&data, 0, options).unwrap_err();
+    assert!(matches!(error, DecompressError::InputSpanExceeded(_)));
+    assert_eq!(error.context().read_index, 8);
+
+    let options = DecompressOptions {
+        max_input_span: Some(data.len()),
+        ..Default::default()
+    };
+    assert_eq!(decompress(&data, 0, options)?.len(), 8);
+
+    Ok(())
+}
+
+#[test]
+fn test_loop_policy() -> anyhow::Result<()> {
+    // re-reads itself forever without writing anything
+    let data = assemble("copy 1 \"aa\"\nbackref count=3 back=2")?;
+
+    for loop_policy in [LoopPolicy::SameIndex(0x10), LoopPolicy::Off] {
+        let options = DecompressOptions {
+            loop_policy,
+            ..Default::default()
+        };
+        assert!(
+            matches!(
+                decompress(&data, 0, options),
+                Err(DecompressError::LoopDetected(_))
+            ),
+            "{:?}",
+            loop_policy
+        );
+    }
+
+    Ok(())
+}
+
+#[test]
+fn test_backref_before_start() -> anyhow::Result<()> {
+    let mut data = assemble("copy 2 \"1234\"")?;
+    let offset = data.len();
+    data.extend(assemble("backref count=3 back=5\nexit")?);
+
+    assert!(matches!(
+        decompress(&data, offset, Default::default()),
+        Err(DecompressError::InvalidOperation(_))
+    ));
+    assert_eq!(
+        decompress(&data, offset, DecompressOptions::permissive())?,
+        [0x12, 0x34]
+    );
+
+    Ok(())
+}
+
+#[test]
+fn test_from_compressed_with() -> anyhow::Result<()> {
+    let data = assemble("repeat 0x200 0x00\nexit")?;
+
+    assert!(PaletteCollection::from_compressed(&data, 0).is_ok());
+    assert!(PaletteCollection::from_compressed_with(&data, 0, DecompressOptions::strict()).is_ok());
+
+    let options = DecompressOptions {
+        max_output: 0x100,
+        ..Default::default()
+    };
+    assert!(matches!(
+        PaletteCollection::from_compressed_with(&data, 0, options),
+        Err(DecompressError::MaxSizeExceeded(_))
+    ));
+
+    Ok(())
+}
@@ -0,0 +1,39 @@
+//! Bytes the routine at $80:A116 reads differently from what the layout of the opcodes suggests.
+
+use thanatos::{Decompressor, NibblePos, Operation, TraceEvent};
+
+#[test]
+fn test_fe_exits() -> anyhow::Result<()> {
+    let rom = [0x00, 0x42, 0xfe, 0x00, 0xff];
+    let result = Decompressor::new(&rom, 0).decompress()?;
+
+    assert_eq!(result.data, [0x42]);
+    assert_eq!(result.bytes_read, 3);
+
+    Ok(())
+}
+
+#[test]
+fn test_nibble_fixed_pos() {
+    // without an initial nibble any of the upper bits moves the fixed nibble down, with one
+    // only bit 4 does
+    for (op_2, expected) in [
+        (0x03, NibblePos::Upper),
+        (0x13, NibblePos::Lower),
+        (0x23, NibblePos::Lower),
+        (0x83, NibblePos::Upper),
+        (0xa3, NibblePos::Upper),
+        (0x93, NibblePos::Lower),
+    ] {
+        let rom = [0x40, op_2, 0x12, 0x34, 0xff, 0x00];
+        let (trace, _) = Decompressor::new(&rom, 0).decompress_traced();
+
+        let Some(TraceEvent::Operation(traced)) = trace.first() else {
+            panic!("no operation decoded for {:#04x}", op_2);
+        };
+        let Operation::CopyNibbleFixed { fixed_pos, .. } = traced.operation else {
+            panic!("unexpected operation {:?}", traced.operation);
+        };
+        assert_eq!(fixed_pos, expected, "{:#04x}", op_2);
+    }
+}
@@ -0,0 +1,92 @@
+use std::fs;
+use thanatos::{assemble, Compressor, Decompressor};
+
+#[test]
+fn test_iterator() -> anyhow::Result<()> {
+    for entry in fs::read_dir("tests/decompress_data")? {
+        let data = fs::read(entry?.path())?;
+        let compressed = Compressor::new(&data).compress();
+
+        let mut decompressor = Decompressor::new(&compressed, 0);
+        let streamed = decompressor.by_ref().collect::<Result<Vec<_>, _>>()?;
+        assert_eq!(streamed, data);
+        assert_eq!(decompressor.bytes_read(), compressed.len());
+        assert!(decompressor.next().is_none());
+    }
+
+    Ok(())
+}
+
+#[test]
+fn test_iterator_stops_early() -> anyhow::Result<()> {
+    let compressed = assemble("copy 4 \"01020304\"\nrepeat 0x1000 0x00\ncopy 1 \"05\"\nexit")?;
+
+    let mut decompressor = Decompressor::new(&compressed, 0);
+    let first = decompressor
+        .by_ref()
+        .take(4)
+        .collect::<Result<Vec<_>, _>>()?;
+    assert_eq!(first, [0x01, 0x02, 0x03, 0x04]);
+    // only the first operation was executed
+    assert_eq!(decompressor.bytes_read(), 5);
+
+    Ok(())
+}
+
+#[test]
+fn test_iterator_error() -> anyhow::Result<()> {
+    let compressed = assemble("copy 2 \"0102\"\nbackread count=2 back=3\nexit")?;
+
+    let mut decompressor = Decompressor::new(&compressed, 0);
+    assert_eq!(decompressor.next().transpose()?, Some(0x01));
+    assert_eq!(decompressor.next().transpose()?, Some(0x02));
+    assert!(matches!(decompressor.next(), Some(Err(_))));
+    assert!(decompressor.next().is_none());
+
+    Ok(())
+}
+
+#[test]
+fn test_iterator_truncated() {
+    // a copy of 4 bytes with only 2 left in the data
+    let mut decompressor = Decompressor::new(&[0x03, 0xaa, 0xbb], 0);
+    assert!(matches!(decompressor.next(), Some(Err(_))));
+    assert!(decompressor.next().is_none());
+}
+
+#[test]
+#[cfg(feature = "std")]
+fn test_read() -> anyhow::Result<()> {
+    use std::io::Read;
+
+    for entry in fs::read_dir("tests/decompress_data")? {
+        let data = fs::read(entry?.path())?;
+        let compressed = Compressor::new(&data).compress();
+
+        let mut decompressor = Decompressor::new(&compressed, 0);
+        let mut streamed = Vec::new();
+        let mut buf = [0; 7];
+        loop {
+            let len = decompressor.read(&mut buf)?;
+            if len == 0 {
+                break;
+            }
+            streamed.extend_from_slice(&buf[..len]);
+        }
+        assert_eq!(streamed, data);
+        assert_eq!(decompressor.bytes_read(), compressed.len());
+    }
+
+    let compressed = assemble("copy 2 \"0102\"")?;
+    let error = Decompressor::new(&compressed, 0)
+        .read_to_end(&mut Vec::new())
+        .unwrap_err();
+    assert_eq!(error.kind(), std::io::ErrorKind::InvalidData);
+
+    let mut decompressor = Decompressor::new(&[0x03, 0xaa, 0xbb], 0);
+    let mut buf = [0; 4];
+    assert!(decompressor.read(&mut buf).is_err());
+    assert_eq!(decompressor.read(&mut buf)?, 0);
+
+    Ok(())
+}
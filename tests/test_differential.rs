@@ -0,0 +1,76 @@
+#![cfg(feature = "decompress-old")]
+
+use std::fs;
+use thanatos::{
+    assemble, compare, compare_all, Compressor, DecompressError, Decompressor, Mismatch,
+};
+
+#[test]
+fn test_differential_compressed() -> anyhow::Result<()> {
+    for entry in fs::read_dir("tests/decompress_data")? {
+        let data = fs::read(entry?.path())?;
+        let compressed = Compressor::new(&data).compress();
+
+        if let Some(disagreement) = compare(&compressed, 0) {
+            panic!("{:#?}", disagreement);
+        }
+    }
+
+    Ok(())
+}
+
+#[test]
+fn test_differential_edge_cases() -> anyhow::Result<()> {
+    let src = r#"
+        copy 2 "12 34"
+        bytes "4f 25 fe dc ba 98 76 54 32 10 00" ; nibble with op2 between 0x20 and 0x7f
+        bytes "40 7a 12"
+        nibble 2 lower fixed=0x0 initial=0x3 "45"
+        nibble 2 upper fixed=0xf initial=0x3 "45"
+        repeat 0x1002 0x00
+        bytes "fe" ; exits just like 0xff
+        copy 1 "00"
+    "#;
+    let compressed = assemble(src)?;
+
+    let result = Decompressor::new(&compressed, 0).decompress()?;
+    assert_eq!(result.bytes_read, compressed.len() - 2);
+
+    if let Some(disagreement) = compare(&compressed, 0) {
+        panic!("{:#?}", disagreement);
+    }
+
+    Ok(())
+}
+
+#[test]
+fn test_differential_long() {
+    // the old routine recurses once per operation
+    let mut compressed = [0x00, 0x42].repeat(0x8000);
+    compressed.push(0xff);
+
+    if let Some(disagreement) = compare(&compressed, 0) {
+        panic!("{:#?}", disagreement);
+    }
+}
+
+#[test]
+fn test_differential_noise() {
+    let mut state = 0x2545f491u32;
+    let mut next = move || {
+        state ^= state << 13;
+        state ^= state >> 17;
+        state ^= state << 5;
+        state as u8
+    };
+    let noise = (0..0x4000).map(|_| next()).collect::<Vec<_>>();
+
+    for disagreement in compare_all(&noise, |_| {}) {
+        // the old routine doesn't stop backrefs from reaching before the start of the data
+        if let Mismatch::NewFailed(DecompressError::InvalidOperation(_)) = disagreement.mismatch {
+            continue;
+        }
+
+        panic!("{:#?}", disagreement);
+    }
+}
@@ -0,0 +1,98 @@
+use thanatos::{assemble, DecompressOptions, Decompressor, EmulationError, SnesAddress, Wram};
+
+#[test]
+fn test_source_crosses_bank() -> anyhow::Result<()> {
+    // starts at $80:FFFC and continues at $81:8000
+    let stream = assemble("copy 8 \"0011223344556677\"\nrepeat 4 0x88\nexit")?;
+    let mut rom = vec![0; 0x10000];
+    rom[0x7ffc..0x7ffc + stream.len()].copy_from_slice(&stream);
+
+    let mut wram = Wram::new();
+    let result = wram.decompress(
+        &rom,
+        SnesAddress::new(0x80, 0xfffc),
+        SnesAddress::new(0x7f, 0x4a00),
+        DecompressOptions::default(),
+    )?;
+
+    let expected = Decompressor::new(&rom, 0x7ffc).decompress()?;
+    assert_eq!(result.data, expected.data);
+    assert_eq!(
+        result.source_end,
+        SnesAddress::new(0x81, 0x8000 + stream.len() as u16 - 4)
+    );
+    assert_eq!(result.dest_end, SnesAddress::new(0x7f, 0x4a0c));
+    assert_eq!(&wram.bank(0x7f).unwrap()[0x4a00..0x4a0c], result.data);
+
+    Ok(())
+}
+
+#[test]
+fn test_dest_wraps() -> anyhow::Result<()> {
+    let rom = [vec![0; 0x8000], assemble("copy 4 \"01020304\"\nexit")?].concat();
+
+    let mut wram = Wram::new();
+    let result = wram.decompress(
+        &rom,
+        SnesAddress::new(0x81, 0x8000),
+        SnesAddress::new(0x7e, 0xfffe),
+        DecompressOptions::default(),
+    )?;
+
+    let bank = wram.bank(0x7e).unwrap();
+    assert_eq!(bank[0xfffe..], [0x01, 0x02]);
+    assert_eq!(bank[..2], [0x03, 0x04]);
+    assert_eq!(result.dest_end, SnesAddress::new(0x7e, 0x0002));
+    assert!(wram.bank(0x7f).unwrap().iter().all(|&value| value == 0));
+
+    Ok(())
+}
+
+#[test]
+fn test_backread_reads_prior_ram() -> anyhow::Result<()> {
+    let stream = assemble("backread count=4 back=6\nexit")?;
+    let rom = [vec![0; 0x8000], stream.clone()].concat();
+
+    let mut wram = Wram::new();
+    wram.bank_mut(0x7e).unwrap()[0xfffe..].copy_from_slice(&[0xaa, 0xbb]);
+    wram.bank_mut(0x7e).unwrap()[..4].copy_from_slice(&[0xcc, 0xdd, 0xee, 0xff]);
+
+    // reaches back across the wrap of X
+    let result = wram.decompress(
+        &rom,
+        SnesAddress::new(0x01, 0x8000),
+        SnesAddress::new(0x7e, 0x0004),
+        DecompressOptions::default(),
+    )?;
+    assert_eq!(result.data, [0xaa, 0xbb, 0xcc, 0xdd]);
+
+    // there is nothing to read without the RAM around it
+    assert!(Decompressor::new(&stream, 0).decompress().is_err());
+
+    Ok(())
+}
+
+#[test]
+fn test_invalid_addresses() {
+    let mut wram = Wram::new();
+    let rom = [0xff; 0x8000];
+
+    assert!(matches!(
+        wram.decompress(
+            &rom,
+            SnesAddress::new(0x00, 0x1000),
+            SnesAddress::new(0x7f, 0x4a00),
+            DecompressOptions::default(),
+        ),
+        Err(EmulationError::NotRom(_))
+    ));
+    assert!(matches!(
+        wram.decompress(
+            &rom,
+            SnesAddress::new(0x80, 0x8000),
+            SnesAddress::new(0x00, 0x4a00),
+            DecompressOptions::default(),
+        ),
+        Err(EmulationError::NotWram(_))
+    ));
+}
@@ -0,0 +1,270 @@
+#![cfg(feature = "std")]
+
+use thanatos::{
+    ColorIndex, Compressable, Decompressor, FreeSpace, MapMode, PaletteCollection, PaletteData,
+    PaletteIndex, PartialTileSet, Pointer, Rom, RomError, TileMap, TileMapEntry, FILL_BYTE,
+};
+
+const REGION: usize = 0x10000;
+const POINTER: usize = 0x20000;
+const PADDING: usize = 0x70000;
+
+/// Tiles that don't compress well, so they take up more space than the ones at [`REGION`].
+fn noisy_tiles(count: usize) -> Vec<u8> {
+    let mut state = 0x1234_5678u32;
+    (0..count * 32)
+        .map(|_| {
+            state ^= state << 13;
+            state ^= state >> 17;
+            state ^= state << 5;
+            state as u8
+        })
+        .collect()
+}
+
+/// A 512KiB LoROM image with a header, compressed tiles at [`REGION`], a FastROM pointer to them
+/// at [`POINTER`] and padding from [`PADDING`] on.
+fn image() -> Vec<u8> {
+    let mut data = vec![0; 0x80000];
+    data[PADDING..].fill(FILL_BYTE);
+
+    let header = MapMode::LoRom.header_offset();
+    data[header..header + 0x15].copy_from_slice(b"PANEL DE PON         ");
+    data[header + 0x15] = 0x30;
+    data[header + 0x17] = 0x09;
+    data[header + 0x3c..header + 0x3e].copy_from_slice(&0x8000u16.to_le_bytes());
+    data[header + 0x1c..header + 0x20].copy_from_slice(&[0xff, 0xff, 0, 0]);
+
+    let tiles = PartialTileSet::try_from_slice(&noisy_tiles(4)).unwrap();
+    let compressed = tiles.compress();
+    data[REGION..REGION + compressed.len()].copy_from_slice(&compressed);
+
+    // $82:8000
+    data[POINTER..POINTER + 3].copy_from_slice(&[0x00, 0x80, 0x82]);
+
+    data
+}
+
+#[test]
+fn test_to_bytes() {
+    let data = noisy_tiles(8);
+    let mut tiles = PartialTileSet::try_from_slice(&data).unwrap();
+    assert_eq!(tiles.to_bytes(), data);
+
+    tiles.tiles_mut()[3].data_mut()[10] = ColorIndex::new(15);
+    let tiles = PartialTileSet::try_from_slice(&tiles.to_bytes()).unwrap();
+    assert_eq!(tiles.tiles()[3].data()[10].index(), 15);
+
+    // colors only have 15 bits
+    let data = noisy_tiles(16)
+        .chunks_exact(2)
+        .flat_map(|color| [color[0], color[1] & 0x7f])
+        .collect::<Vec<_>>();
+    let mut palettes = PaletteCollection::try_from_slice(&data).unwrap();
+    assert_eq!(palettes.to_bytes(), data);
+
+    palettes[PaletteIndex::new(1)][ColorIndex::new(2)] = thanatos::Color([0xf8, 0x00, 0x08]);
+    let palettes = PaletteCollection::try_from_slice(&palettes.to_bytes()).unwrap();
+    assert_eq!(
+        palettes[PaletteIndex::new(1)][ColorIndex::new(2)],
+        thanatos::Color([0xf8, 0x00, 0x08])
+    );
+
+    let data = noisy_tiles(1);
+    let mut tile_map = TileMap::try_from_slice(&data).unwrap();
+    assert_eq!(tile_map.to_bytes(), data);
+
+    tile_map[0] = TileMapEntry::new(0x4005);
+    assert_eq!(tile_map.to_bytes()[..2], [0x05, 0x40]);
+}
+
+#[test]
+fn test_palette_data() -> anyhow::Result<()> {
+    // a single palette, like the partial regions maps place with a start offset
+    let data = noisy_tiles(1);
+    let palettes = PaletteData::try_from_slice(&data)?;
+    assert_eq!(palettes.len(), 1);
+    assert_eq!(palettes.to_bytes(), data);
+
+    // the unused top bit of each color survives the round trip through the ROM
+    let data = noisy_tiles(4)
+        .chunks_exact(2)
+        .flat_map(|color| [color[0], color[1] | 0x80])
+        .collect::<Vec<_>>();
+    let compressed = PaletteData::try_from_slice(&data)?.compress();
+    assert_eq!(Decompressor::new(&compressed, 0).decompress()?.data, data);
+    assert_eq!(
+        PaletteData::from_compressed(&compressed, 0)?.to_bytes(),
+        data
+    );
+
+    for len in [0, 16, 33, 544] {
+        assert!(
+            PaletteData::try_from_slice(&vec![0; len]).is_err(),
+            "{}",
+            len
+        );
+    }
+
+    Ok(())
+}
+
+#[test]
+fn test_free_space() {
+    let mut data = vec![0; 0x20000];
+    data[0x100..0x110].fill(FILL_BYTE);
+    data[0x7ff0..0x8100].fill(FILL_BYTE);
+    data[0x1ff00..].fill(FILL_BYTE);
+
+    let mut free = FreeSpace::find(&data, 0x20);
+    assert_eq!(free.ranges(), [0x7ff0..0x8100, 0x1ff00..0x20000]);
+    assert_eq!(free.len(), 0x210);
+
+    // the last range is used first, without crossing into the next bank
+    assert_eq!(free.allocate(0x80, 0x8000), Some(0x1ff00));
+    assert_eq!(free.allocate(0x80, 0x8000), Some(0x1ff80));
+    assert_eq!(free.allocate(0x80, 0x8000), Some(0x8000));
+    assert_eq!(free.allocate(0x100, 0x8000), None);
+
+    free.add(0x8080..0x8200);
+    assert_eq!(free.ranges(), [0x7ff0..0x8000, 0x8080..0x8200]);
+
+    free.remove(0x7ff8..0x8100);
+    assert_eq!(free.ranges(), [0x7ff0..0x7ff8, 0x8100..0x8200]);
+}
+
+#[test]
+fn test_insert_in_place() -> anyhow::Result<()> {
+    let data = image();
+    let mut rom = Rom::new(&data);
+    let old_len = Decompressor::new(rom.data(), REGION)
+        .decompress()?
+        .bytes_read;
+
+    // empty tiles compress to almost nothing
+    let tiles = PartialTileSet::try_from_slice(&[0; 4 * 32])?;
+    let compressed = tiles.compress();
+
+    let mut free = FreeSpace::find(rom.data(), 0x100);
+    let insertion = rom.insert(REGION, &compressed, &[], &mut free)?;
+    assert_eq!(insertion.offset, REGION);
+    assert!(!insertion.relocated);
+
+    let result = Decompressor::new(rom.data(), REGION).decompress()?;
+    assert_eq!(result.data, tiles.to_bytes());
+    assert!(rom.data()[REGION + compressed.len()..REGION + old_len]
+        .iter()
+        .all(|&byte| byte == FILL_BYTE));
+    assert!(free
+        .ranges()
+        .contains(&(REGION + compressed.len()..REGION + old_len)));
+
+    Ok(())
+}
+
+#[test]
+fn test_insert_relocated() -> anyhow::Result<()> {
+    let data = image();
+    let mut rom = Rom::new(&data);
+    assert_eq!(rom.pointers_to(REGION), [Pointer::Long(POINTER)]);
+
+    let tiles = PartialTileSet::try_from_slice(&noisy_tiles(16))?;
+    let compressed = tiles.compress();
+
+    let mut free = FreeSpace::find(rom.data(), 0x100);
+    let insertion = rom.insert(REGION, &compressed, &[Pointer::Long(POINTER)], &mut free)?;
+    assert!(insertion.relocated);
+    assert!(insertion.offset >= PADDING);
+    assert_eq!(insertion.pointers, [Pointer::Long(POINTER)]);
+
+    let result = Decompressor::new(rom.data(), insertion.offset).decompress()?;
+    assert_eq!(result.data, tiles.to_bytes());
+
+    // still points through the FastROM banks
+    let address = rom.address(insertion.offset);
+    assert_eq!(
+        rom.data()[POINTER..POINTER + 3],
+        [
+            address.addr as u8,
+            (address.addr >> 8) as u8,
+            address.bank | 0x80
+        ]
+    );
+    assert_eq!(rom.data()[REGION], FILL_BYTE);
+    assert_eq!(rom.pointers_to(insertion.offset), [Pointer::Long(POINTER)]);
+    assert_eq!(rom.crc(), crc32fast::hash(rom.data()));
+
+    let checksum = rom.fix_checksum().expect("header wasn't found");
+    let header = rom.header().unwrap();
+    assert_eq!(header.checksum, checksum);
+    assert_eq!(header.checksum, rom.checksum());
+    assert!(header.complement_matches());
+    assert_eq!(rom.crc(), crc32fast::hash(rom.data()));
+    assert_eq!(Rom::new(rom.data()).header(), rom.header());
+
+    Ok(())
+}
+
+#[test]
+fn test_insert_pointers() -> anyhow::Result<()> {
+    let mut data = image();
+    // data that happens to look like a pointer to the region
+    let lookalike = 0x30000;
+    data[lookalike..lookalike + 3].copy_from_slice(&[0x00, 0x80, 0x82]);
+    let mut rom = Rom::new(&data);
+    assert_eq!(
+        rom.pointers_to(REGION),
+        [Pointer::Long(POINTER), Pointer::Long(lookalike)]
+    );
+
+    let compressed = PartialTileSet::try_from_slice(&noisy_tiles(16))?.compress();
+    let mut free = FreeSpace::find(rom.data(), 0x100);
+
+    // moving the data without knowing what points to it is refused
+    assert!(matches!(
+        rom.insert(REGION, &compressed, &[], &mut free),
+        Err(RomError::NoPointers(REGION))
+    ));
+    assert!(matches!(
+        rom.insert(
+            REGION,
+            &compressed,
+            &[Pointer::Long(POINTER + 1)],
+            &mut free
+        ),
+        Err(RomError::NotAPointer { .. })
+    ));
+    assert_eq!(rom.data(), data);
+
+    // only the pointer that was confirmed is changed
+    let insertion = rom.insert(REGION, &compressed, &[Pointer::Long(POINTER)], &mut free)?;
+    assert!(insertion.relocated);
+    assert_ne!(rom.data()[POINTER..POINTER + 3], [0x00, 0x80, 0x82]);
+    assert_eq!(rom.data()[lookalike..lookalike + 3], [0x00, 0x80, 0x82]);
+
+    Ok(())
+}
+
+#[test]
+fn test_insert_short_pointer() -> anyhow::Result<()> {
+    let mut data = image();
+    // LDY #$8000, with the bank set elsewhere
+    let ldy = 0x20100;
+    data[ldy..ldy + 3].copy_from_slice(&[0xa0, 0x00, 0x80]);
+    // some room in the bank of the region, besides the padding at the end
+    data[0x14000..0x18000].fill(FILL_BYTE);
+    let mut rom = Rom::new(&data);
+    assert!(rom.pointers_to(REGION).contains(&Pointer::Short(ldy + 1)));
+
+    let compressed = PartialTileSet::try_from_slice(&noisy_tiles(16))?.compress();
+    let mut free = FreeSpace::find(rom.data(), 0x100);
+    let insertion = rom.insert(REGION, &compressed, &[Pointer::Short(ldy + 1)], &mut free)?;
+    assert!(insertion.relocated);
+    assert!((0x14000..0x18000).contains(&insertion.offset));
+
+    let address = rom.address(insertion.offset);
+    assert_eq!(address.bank, rom.address(REGION).bank);
+    assert_eq!(rom.data()[ldy + 1..ldy + 3], address.addr.to_le_bytes());
+
+    Ok(())
+}
@@ -0,0 +1,304 @@
+#![cfg(feature = "std")]
+
+use std::{fs, path::PathBuf};
+use thanatos::{IpsBase, MapTarget, MappedRom, PatchError, PatchFormat, Rom, RomError, RomMap};
+
+/// A ROM whose bytes don't repeat much, with some changes to it that grow it by a bit.
+fn roms() -> (Vec<u8>, Vec<u8>) {
+    let original = (0..0x20000u32)
+        .map(|i| (i.wrapping_mul(2654435761) >> 24) as u8)
+        .collect::<Vec<_>>();
+
+    let mut modified = original.clone();
+    modified[0x10..0x14].copy_from_slice(b"ABCD");
+    modified[0x8000..0x9000].fill(0xff);
+    modified[0x12345] ^= 0x40;
+    modified.extend_from_slice(&[0x00; 0x100]);
+
+    (original, modified)
+}
+
+fn temp_path(name: &str) -> PathBuf {
+    std::env::temp_dir().join(format!("thanatos_test_{}_{}", std::process::id(), name))
+}
+
+#[test]
+fn test_ips() -> anyhow::Result<()> {
+    let original = vec![0; 0x40];
+    let mut modified = original.clone();
+    modified[0x02] = 1;
+    // close enough to share a record
+    modified[0x06] = 2;
+    modified[0x20..0x30].fill(0xff);
+
+    let patch = PatchFormat::Ips.create(&original, &modified)?;
+    #[rustfmt::skip]
+    assert_eq!(patch, [
+        b"PATCH".as_slice(),
+        &[0x00, 0x00, 0x02, 0x00, 0x05, 1, 0, 0, 0, 2],
+        &[0x00, 0x00, 0x20, 0x00, 0x00, 0x00, 0x10, 0xff],
+        b"EOF",
+    ].concat());
+
+    // growing and shrinking
+    let patch = PatchFormat::Ips.create(&original, &[0; 0x42])?;
+    assert_eq!(
+        patch,
+        [b"PATCH".as_slice(), &[0, 0, 0x40, 0, 2, 0, 0], b"EOF"].concat()
+    );
+    let patch = PatchFormat::Ips.create(&original, &[0; 0x20])?;
+    assert_eq!(patch, [b"PATCH".as_slice(), b"EOF", &[0, 0, 0x20]].concat());
+
+    assert_eq!(
+        PatchFormat::Ips.create(&[], &vec![0; 0x1000001]),
+        Err(PatchError::TooLarge(0x1000001))
+    );
+
+    Ok(())
+}
+
+#[test]
+fn test_ips_eof_offset() -> anyhow::Result<()> {
+    let original = vec![0; 0x454f50];
+    let mut modified = original.clone();
+    modified[0x454f46] = 1;
+
+    let patch = PatchFormat::Ips.create(&original, &modified)?;
+    assert_eq!(&patch[5..10], [0x45, 0x4f, 0x45, 0x00, 0x02]);
+    assert_eq!(&patch[10..12], [0, 1]);
+
+    Ok(())
+}
+
+#[test]
+fn test_bps() -> anyhow::Result<()> {
+    let source = b"thanatos".repeat(4);
+    let mut target = source.clone();
+    target[4..6].copy_from_slice(b"AT");
+    target.extend_from_slice(&[0xff; 8]);
+
+    let patch = PatchFormat::Bps.create(&source, &target)?;
+    let body = &patch[..patch.len() - 12];
+    #[rustfmt::skip]
+    assert_eq!(body, [
+        b"BPS1".as_slice(),
+        // sizes and metadata
+        &[0x80 | 32, 0x80 | 40, 0x80],
+        // source read 4, target read "AT"
+        &[0x80 | 3 << 2, 0x80 | 1 << 2 | 1], b"AT",
+        // source read 26, target read 0xff, target copy 7 from the byte before
+        &[0x80 | 25 << 2], &[0x80 | 1, 0xff], &[0x80 | 6 << 2 | 3, 0x80 | 32 << 1],
+    ].concat());
+
+    let footer = &patch[patch.len() - 12..];
+    assert_eq!(footer[..4], crc32fast::hash(&source).to_le_bytes());
+    assert_eq!(footer[4..8], crc32fast::hash(&target).to_le_bytes());
+    assert_eq!(
+        footer[8..],
+        crc32fast::hash(&patch[..patch.len() - 4]).to_le_bytes()
+    );
+
+    Ok(())
+}
+
+#[test]
+fn test_apply() -> anyhow::Result<()> {
+    let (original, modified) = roms();
+    let shrunk = &modified[..0x1f000];
+
+    for format in PatchFormat::ALL {
+        for target in [modified.as_slice(), shrunk] {
+            let patch = format.create(&original, target)?;
+            assert_eq!(PatchFormat::detect(&patch), Some(format));
+            assert_eq!(format.apply(&original, &patch)?, target, "{:?}", format);
+        }
+    }
+    assert_eq!(PatchFormat::detect(b"not a patch"), None);
+
+    Ok(())
+}
+
+#[test]
+fn test_apply_errors() -> anyhow::Result<()> {
+    let (original, modified) = roms();
+
+    let patch = PatchFormat::Ips.create(&original, &modified)?;
+    assert_eq!(
+        PatchFormat::Ips.apply(&original, &patch[..patch.len() - 10]),
+        Err(PatchError::Truncated)
+    );
+
+    let patch = PatchFormat::Bps.create(&original, &modified)?;
+    assert_eq!(
+        PatchFormat::Bps.apply(&modified, &patch),
+        Err(PatchError::SourceMismatch {
+            expected: crc32fast::hash(&original),
+            actual: crc32fast::hash(&modified),
+        })
+    );
+
+    let mut damaged = patch.clone();
+    damaged[10] ^= 1;
+    assert_eq!(
+        PatchFormat::Bps.apply(&original, &damaged),
+        Err(PatchError::Corrupt)
+    );
+
+    Ok(())
+}
+
+/// A BPS patch for `source` with the given sizes and actions, claiming to produce nothing.
+fn hostile_bps(source: &[u8], sizes: [usize; 3], actions: &[usize]) -> Vec<u8> {
+    let mut patch = b"BPS1".to_vec();
+    for mut number in sizes.into_iter().chain(actions.iter().copied()) {
+        loop {
+            let bits = (number & 0x7f) as u8;
+            number >>= 7;
+            if number == 0 {
+                patch.push(0x80 | bits);
+                break;
+            }
+            patch.push(bits);
+            number -= 1;
+        }
+    }
+
+    patch.extend_from_slice(&crc32fast::hash(source).to_le_bytes());
+    patch.extend_from_slice(&crc32fast::hash(&[]).to_le_bytes());
+    let crc = crc32fast::hash(&patch);
+    patch.extend_from_slice(&crc.to_le_bytes());
+    patch
+}
+
+#[test]
+fn test_apply_hostile_sizes() {
+    let source = [0; 0x10];
+
+    // nothing is reserved for a target that can't exist
+    let patch = hostile_bps(&source, [source.len(), usize::MAX / 2, 0], &[]);
+    assert_eq!(
+        PatchFormat::Bps.apply(&source, &patch),
+        Err(PatchError::Corrupt)
+    );
+
+    let patch = hostile_bps(&source, [source.len(), 0, usize::MAX / 2], &[]);
+    assert_eq!(
+        PatchFormat::Bps.apply(&source, &patch),
+        Err(PatchError::Truncated)
+    );
+
+    // a target copy repeating the first byte far past the target size
+    let patch = hostile_bps(&source, [source.len(), 4, 0], &[0, usize::MAX - 3, 0]);
+    assert_eq!(
+        PatchFormat::Bps.apply(&source, &patch),
+        Err(PatchError::OutOfBounds)
+    );
+}
+
+#[test]
+fn test_open_patched() -> anyhow::Result<()> {
+    let (original, modified) = roms();
+    let mut step = modified.clone();
+    step[0x20] = 0x42;
+
+    let rom_path = temp_path("base.sfc");
+    let ips_path = temp_path("first.ips");
+    let bps_path = temp_path("second.bps");
+    fs::write(&rom_path, &original)?;
+    fs::write(&ips_path, PatchFormat::Ips.create(&original, &modified)?)?;
+    fs::write(&bps_path, PatchFormat::Bps.create(&modified, &step)?)?;
+
+    let mut rom = Rom::open_patched(&rom_path, &[&ips_path, &bps_path], None)?;
+    assert_eq!(rom.data(), step);
+    assert_eq!(rom.crc(), crc32fast::hash(&step));
+    assert_eq!(rom.base_crc(), crc32fast::hash(&original));
+
+    // maps are picked by the unpatched ROM unless asked otherwise
+    let map = |crc: u32| {
+        RomMap::parse(&format!(
+            r#"
+            sprite = []
+            palette = []
+            tileset = []
+
+            [[supported_roms]]
+            name = "test"
+            crc = {}
+            "#,
+            crc
+        ))
+    };
+    let base_map = map(crc32fast::hash(&original))?;
+    let patched_map = map(crc32fast::hash(&step))?;
+    assert_eq!(rom.map_target(), MapTarget::Base);
+    assert!(base_map.is_compatible_with(&rom));
+    assert!(!patched_map.is_compatible_with(&rom));
+    assert_eq!(
+        MappedRom::new(&rom, &base_map)?.metadata.crc,
+        rom.base_crc()
+    );
+
+    rom.set_map_target(MapTarget::Patched);
+    assert!(!base_map.is_compatible_with(&rom));
+    assert!(patched_map.is_compatible_with(&rom));
+
+    // the BPS patch only applies on top of the IPS one
+    let error = Rom::open_patched(&rom_path, &[&bps_path], None).unwrap_err();
+    assert!(matches!(
+        error,
+        RomError::Patch {
+            source: PatchError::SourceMismatch { .. },
+            ..
+        }
+    ));
+
+    for path in [rom_path, ips_path, bps_path] {
+        fs::remove_file(path)?;
+    }
+
+    Ok(())
+}
+
+#[test]
+fn test_open_patched_copier_header() -> anyhow::Result<()> {
+    let (original, modified) = roms();
+    let header = vec![0; 0x200];
+    let headered = [header.as_slice(), &original].concat();
+    let headered_modified = [header.as_slice(), &modified].concat();
+
+    let rom_path = temp_path("headered.smc");
+    let patch_path = temp_path("headered.patch");
+    fs::write(&rom_path, &headered)?;
+
+    // BPS patches are matched to the image they were made for by their checksum
+    for (source, target) in [(&original, &modified), (&headered, &headered_modified)] {
+        fs::write(&patch_path, PatchFormat::Bps.create(source, target)?)?;
+
+        let rom = Rom::open_patched(&rom_path, &[&patch_path], None)?;
+        assert!(rom.had_copier_header());
+        assert_eq!(rom.data(), modified);
+        assert_eq!(rom.base_crc(), crc32fast::hash(&original));
+    }
+
+    // IPS patches can't tell, so they need to be told
+    fs::write(
+        &patch_path,
+        PatchFormat::Ips.create(&headered, &headered_modified)?,
+    )?;
+    assert!(matches!(
+        Rom::open_patched(&rom_path, &[&patch_path], None),
+        Err(RomError::AmbiguousIpsPatch(_))
+    ));
+    let rom = Rom::open_patched(&rom_path, &[&patch_path], Some(IpsBase::Headered))?;
+    assert_eq!(rom.data(), modified);
+
+    fs::write(&patch_path, PatchFormat::Ips.create(&original, &modified)?)?;
+    let rom = Rom::open_patched(&rom_path, &[&patch_path], Some(IpsBase::Stripped))?;
+    assert_eq!(rom.data(), modified);
+
+    for path in [rom_path, patch_path] {
+        fs::remove_file(path)?;
+    }
+
+    Ok(())
+}
@@ -0,0 +1,89 @@
+#![cfg(feature = "std")]
+
+use thanatos::{MapMode, Rom};
+
+/// An image of `size` bytes with a valid header for `map_mode` and a correct checksum.
+fn image(size: usize, map_mode: MapMode) -> Vec<u8> {
+    let mut data = (0..size).map(|i| (i * 7 / 3) as u8).collect::<Vec<_>>();
+
+    let header = map_mode.header_offset();
+    data[header..header + 0x15].copy_from_slice(b"PANEL DE PON         ");
+    data[header + 0x15] = match map_mode {
+        MapMode::LoRom => 0x20,
+        MapMode::HiRom => 0x31,
+        MapMode::ExHiRom => 0x35,
+    };
+    data[header + 0x16] = 0x02;
+    data[header + 0x17] = 0x0a;
+    data[header + 0x18] = 0x03;
+    data[header + 0x19] = 0x00;
+    data[header + 0x1b] = 0x01;
+    data[header + 0x3c..header + 0x3e].copy_from_slice(&0x8000u16.to_le_bytes());
+
+    // checksum and complement always add up to the same byte sum
+    data[header + 0x1c..header + 0x20].copy_from_slice(&[0xff, 0xff, 0, 0]);
+    let checksum = Rom::new(&data).checksum();
+    data[header + 0x1c..header + 0x1e].copy_from_slice(&(!checksum).to_le_bytes());
+    data[header + 0x1e..header + 0x20].copy_from_slice(&checksum.to_le_bytes());
+
+    data
+}
+
+#[test]
+fn test_header() {
+    let data = image(0x100000, MapMode::LoRom);
+    let rom = Rom::new(&data);
+    let header = rom.header().expect("header wasn't found");
+
+    assert_eq!(header.map_mode, MapMode::LoRom);
+    assert!(!header.fast_rom);
+    assert_eq!(header.title, "PANEL DE PON");
+    assert_eq!(header.chipset, 0x02);
+    assert_eq!(header.rom_size, 0x100000);
+    assert_eq!(header.ram_size, 0x2000);
+    assert_eq!(header.region_name(), Some("Japan"));
+    assert_eq!(header.version, 1);
+    assert!(header.complement_matches());
+    assert_eq!(header.checksum, rom.checksum());
+
+    let data = image(0x100000, MapMode::HiRom);
+    let header = Rom::new(&data).header().cloned().unwrap();
+    assert_eq!(header.map_mode, MapMode::HiRom);
+    assert!(header.fast_rom);
+
+    assert!(Rom::new(&[0; 0x10000]).header().is_none());
+}
+
+#[test]
+fn test_checksum() {
+    // 1.5MiB, the last half MiB counts twice
+    let data = image(0x180000, MapMode::LoRom);
+    let rom = Rom::new(&data);
+    assert_eq!(rom.header().unwrap().checksum, rom.checksum());
+
+    let mut bad_dump = data.clone();
+    bad_dump[0x1234] ^= 0x10;
+    let rom = Rom::new(&bad_dump);
+    assert_ne!(rom.header().unwrap().checksum, rom.checksum());
+}
+
+#[test]
+fn test_copier_header() {
+    let data = image(0x80000, MapMode::LoRom);
+    let mut headered = vec![0; 0x200];
+    headered[..3].copy_from_slice(&[0x40, 0x00, 0x00]);
+    headered.extend_from_slice(&data);
+
+    let plain = Rom::new_stripping_header(&data);
+    let stripped = Rom::new_stripping_header(&headered);
+    assert!(!plain.had_copier_header());
+    assert!(stripped.had_copier_header());
+    assert_eq!(stripped.data(), plain.data());
+    assert_eq!(stripped.crc(), plain.crc());
+    assert_eq!(stripped.header(), plain.header());
+
+    // only stripped when asked to
+    let unstripped = Rom::new(&headered);
+    assert!(!unstripped.had_copier_header());
+    assert_eq!(unstripped.data(), headered);
+}
@@ -0,0 +1,233 @@
+#![cfg(feature = "std")]
+
+mod common;
+
+use thanatos::{Classifier, Compressor, DecompressOptions, RegionKind, RomMap, ScanResult, Score};
+
+fn palettes() -> Vec<u8> {
+    (0..256u16)
+        .flat_map(|i| (i.wrapping_mul(0x0421) & 0x7fff).to_le_bytes())
+        .collect()
+}
+
+fn tilemap() -> Vec<u8> {
+    (0..64u16)
+        .flat_map(|i| (i | 0x0400 | (i & 1) << 15).to_le_bytes())
+        .collect()
+}
+
+#[test]
+fn test_classify() {
+    let classifier = Classifier::default();
+
+    assert_eq!(classifier.classify(&palettes()), Some(RegionKind::Palette));
+    assert_eq!(
+        classifier.classify(&common::noise(0x1000)),
+        Some(RegionKind::TileSet)
+    );
+    assert_eq!(classifier.classify(&tilemap()), Some(RegionKind::TileMap));
+
+    // the top bit of a color is never set
+    let mut bad_palettes = palettes();
+    bad_palettes[33] |= 0x80;
+    assert_ne!(
+        classifier.classify(&bad_palettes),
+        Some(RegionKind::Palette)
+    );
+
+    assert_eq!(classifier.classify(&common::noise(0x1001)), None);
+    assert_eq!(classifier.classify(&[0; 512]), None);
+    assert_eq!(classifier.classify(&tilemap()[..16]), None);
+}
+
+#[test]
+fn test_scan() {
+    let streams = [palettes(), common::noise(0x1000), tilemap()];
+
+    // separated by a few bytes nothing can be decoded from
+    let mut rom = Vec::new();
+    let mut offsets = Vec::new();
+    for stream in &streams {
+        rom.extend([0xc0; 3]);
+        offsets.push(rom.len());
+        rom.extend(Compressor::new(stream).compress());
+    }
+
+    let mut streamed = Vec::new();
+    let results = thanatos::scan(
+        &rom,
+        &DecompressOptions::strict(),
+        &Classifier::default(),
+        |_| {},
+        |result| streamed.push((result.offset, result.aliases.clone())),
+    );
+    let expected = results
+        .iter()
+        .map(|result| (result.offset, result.aliases.clone()))
+        .collect::<Vec<_>>();
+    assert_eq!(streamed, expected);
+
+    let kinds = [
+        RegionKind::Palette,
+        RegionKind::TileSet,
+        RegionKind::TileMap,
+    ];
+    for ((stream, offset), kind) in streams.iter().zip(offsets.iter().copied()).zip(kinds) {
+        // garbage before a stream may decode into it, which makes the stream an alias
+        let result = results
+            .iter()
+            .find(|result| result.offset == offset || result.aliases.contains(&offset))
+            .expect("stream wasn't found");
+
+        assert!(result.data.ends_with(stream));
+        assert_eq!(result.kind, Some(kind));
+    }
+
+    let score = |offset| {
+        results
+            .iter()
+            .find(|result| result.offset == offset || result.aliases.contains(&offset))
+            .unwrap()
+            .score
+    };
+
+    // random tiles don't compress and have noisy bit planes
+    let noise = score(offsets[1]);
+    assert!(noise.literal.unwrap() > 0.95);
+    assert!(noise.entropy.unwrap() > 0.9);
+    assert!(noise.value() < 0.5);
+
+    // palettes are judged without the literal data
+    assert_eq!(score(offsets[0]).literal, None);
+
+    assert!(results
+        .windows(2)
+        .all(|pair| pair[0].offset < pair[1].offset));
+}
+
+fn result(offset: usize, end: usize, kind: Option<RegionKind>, data: &[u8]) -> ScanResult {
+    ScanResult {
+        offset,
+        end,
+        kind,
+        score: Score {
+            ratio: 0.5,
+            literal: None,
+            entropy: None,
+            redundant_tiles: None,
+        },
+        data: data.to_vec(),
+        aliases: Vec::new(),
+    }
+}
+
+#[test]
+fn test_deduplicate() {
+    let results = thanatos::deduplicate(vec![
+        result(0x12, 0x20, None, &[3, 4]),
+        result(0x10, 0x20, Some(RegionKind::TileMap), &[1, 2, 3, 4]),
+        // same end, but not a suffix
+        result(0x11, 0x20, None, &[5, 3, 4]),
+        // a suffix that looks like something else
+        result(0x13, 0x20, Some(RegionKind::Palette), &[4]),
+        result(0x14, 0x30, None, &[4]),
+    ]);
+
+    let summary = results
+        .iter()
+        .map(|result| (result.offset, result.aliases.clone()))
+        .collect::<Vec<_>>();
+    assert_eq!(
+        summary,
+        [
+            (0x10, vec![0x12]),
+            (0x11, vec![]),
+            (0x13, vec![]),
+            (0x14, vec![])
+        ]
+    );
+}
+
+#[test]
+fn test_draft_map() {
+    let results = [
+        result(0x100, 0x120, Some(RegionKind::Palette), &palettes()),
+        result(0xa00, 0xa20, Some(RegionKind::Palette), &palettes()),
+        result(0x200, 0x300, Some(RegionKind::TileSet), &[0; 64 * 32]),
+        result(0x300, 0x400, Some(RegionKind::TileSet), &[0; 32 * 32]),
+        result(0x900, 0xa00, Some(RegionKind::TileSet), &[0; 32 * 32]),
+        // uses tile 0x3f, so only the first tile set covers it
+        result(0x500, 0x540, Some(RegionKind::TileMap), &tilemap()),
+        result(0x600, 0x640, Some(RegionKind::TileMap), &tilemap()[..40]),
+        result(0x800, 0x900, None, &[1, 2, 3]),
+    ];
+
+    let map = RomMap::parse(&thanatos::draft_map(&results, "test", 0x1234))
+        .expect("draft map doesn't parse");
+
+    assert_eq!(map.supported_roms[0].crc, 0x1234);
+    assert_eq!(map.palettes[0].name, "palette-100");
+    assert_eq!(map.palettes[0].layout[0].region, 0x100);
+    assert_eq!(map.tilesets.len(), 3);
+
+    let sprite = &map.sprites[0];
+    assert_eq!(sprite.name, "sprite-500");
+    assert_eq!(sprite.size, (32, 2));
+    assert_eq!(sprite.tileset, "tileset-200");
+    assert_eq!(sprite.palette, "palette-100");
+    assert_eq!(sprite.layout_region, 0x500);
+
+    // 20 entries using tiles up to 0x13, the closer of the smallest sets
+    let sprite = &map.sprites[1];
+    assert_eq!(sprite.size, (5, 4));
+    assert_eq!(sprite.tileset, "tileset-300");
+    // and the closer palette
+    assert_eq!(sprite.palette, "palette-a00");
+
+    assert!(RomMap::parse(&thanatos::draft_map(&[], "empty", 0)).is_ok());
+
+    // nothing holds tile 0x3f, so the sprite is only left as a comment
+    let text = thanatos::draft_map(
+        &[results[0].clone(), results[3].clone(), results[5].clone()],
+        "test",
+        0,
+    );
+    let map = RomMap::parse(&text).expect("draft map doesn't parse");
+    assert!(map.sprites.is_empty());
+    assert!(text.contains("# no tile set has tile 0x3f\n# [[sprite]]\n# name = \"sprite-500\""));
+}
+
+#[test]
+fn test_draft_map_loads() -> anyhow::Result<()> {
+    use thanatos::{MappedRom, Rom};
+
+    let streams = [
+        (Some(RegionKind::Palette), palettes()),
+        // a single palette, only usable on top of a full set
+        (Some(RegionKind::Palette), palettes()[..32].to_vec()),
+        (Some(RegionKind::TileSet), common::noise(64 * 32)),
+        (Some(RegionKind::TileMap), tilemap()),
+    ];
+
+    let mut rom = Vec::new();
+    let mut results = Vec::new();
+    for (kind, stream) in &streams {
+        let offset = rom.len();
+        rom.extend(Compressor::new(stream).compress());
+        results.push(result(offset, rom.len(), *kind, stream));
+    }
+
+    let text = thanatos::draft_map(&results, "test", crc32fast::hash(&rom));
+    let map = RomMap::parse(&text)?;
+    assert_eq!(map.palettes.len(), 1);
+    assert!(text.contains(&format!(
+        "# 1 palette(s), needs a full set of palettes under it and where it starts\n# [[palette]]\n# name = \"palette-{:x}\"",
+        results[1].offset
+    )));
+
+    let rom = Rom::new(&rom);
+    let mapped = MappedRom::new(&rom, &map)?;
+    assert_eq!(mapped.sprites.len(), 1);
+
+    Ok(())
+}
@@ -0,0 +1,106 @@
+#![cfg(feature = "std")]
+
+mod common;
+
+use std::{fs, path::PathBuf};
+use thanatos::{Classifier, Compressor, DecompressOptions, LoopPolicy, ScanCache};
+
+fn rom() -> Vec<u8> {
+    let mut rom = common::noise(0x14000);
+    let tiles = (0..0x800u32).map(|i| (i / 7) as u8).collect::<Vec<_>>();
+    let compressed = Compressor::new(&tiles).compress();
+    rom[0x12000..0x12000 + compressed.len()].copy_from_slice(&compressed);
+    rom
+}
+
+fn cache_path(name: &str) -> PathBuf {
+    let path = std::env::temp_dir().join(format!(
+        "thanatos_test_{}_{}.cache",
+        name,
+        std::process::id()
+    ));
+    let _ = fs::remove_file(&path);
+    path
+}
+
+fn summary(results: &[thanatos::ScanResult]) -> Vec<(usize, usize, usize)> {
+    results
+        .iter()
+        .map(|result| (result.offset, result.end, result.data.len()))
+        .collect()
+}
+
+#[test]
+fn test_scan_cache() -> anyhow::Result<()> {
+    let rom = rom();
+    let options = DecompressOptions::strict();
+    let classifier = Classifier::default();
+    let path = cache_path("resume");
+
+    let expected = thanatos::scan(&rom, &options, &classifier, |_| {}, |_| {});
+    assert!(expected.iter().any(|result| result.offset == 0x12000));
+
+    let mut cache = ScanCache::open(&path, 0x1234, &options)?;
+    let results = thanatos::scan_cached(&rom, &options, &classifier, &mut cache, |_| {}, |_| {})?;
+    assert_eq!(summary(&results), summary(&expected));
+    assert_eq!(cache.done(), rom.len());
+    let hits = cache.hits().to_vec();
+    drop(cache);
+
+    // nothing left to decode
+    let mut cache = ScanCache::open(&path, 0x1234, &options)?;
+    assert_eq!(cache.hits(), hits);
+    let results = thanatos::scan_cached(&rom, &options, &classifier, &mut cache, |_| {}, |_| {})?;
+    assert_eq!(summary(&results), summary(&expected));
+    drop(cache);
+
+    // cut short in the middle of the second chunk
+    let contents = fs::read_to_string(&path)?;
+    let second_chunk = contents.find("done").unwrap() + 20;
+    fs::write(&path, &contents[..second_chunk])?;
+
+    let mut cache = ScanCache::open(&path, 0x1234, &options)?;
+    assert_eq!(cache.done(), 0x10000);
+    assert!(cache.hits().len() < hits.len());
+    let results = thanatos::scan_cached(&rom, &options, &classifier, &mut cache, |_| {}, |_| {})?;
+    assert_eq!(summary(&results), summary(&expected));
+    assert_eq!(cache.hits(), hits);
+    drop(cache);
+    assert_eq!(fs::read_to_string(&path)?, contents);
+
+    fs::remove_file(&path)?;
+    Ok(())
+}
+
+#[test]
+fn test_scan_cache_key() -> anyhow::Result<()> {
+    let rom = rom();
+    let options = DecompressOptions::strict();
+    let path = cache_path("key");
+
+    let mut cache = ScanCache::open(&path, 0x1234, &options)?;
+    thanatos::scan_cached(
+        &rom,
+        &options,
+        &Classifier::default(),
+        &mut cache,
+        |_| {},
+        |_| {},
+    )?;
+    drop(cache);
+
+    let cache = ScanCache::open(&path, 0x5678, &options)?;
+    assert_eq!(cache.done(), 0);
+    assert!(cache.hits().is_empty());
+    drop(cache);
+
+    let other = DecompressOptions {
+        loop_policy: LoopPolicy::Off,
+        ..options
+    };
+    let cache = ScanCache::open(&path, 0x5678, &other)?;
+    assert_eq!(cache.done(), 0);
+
+    fs::remove_file(&path)?;
+    Ok(())
+}
@@ -0,0 +1,57 @@
+#![cfg(feature = "std")]
+
+use thanatos::{Compressable, PartialTileSet, TileSheet, BW_PALETTE};
+
+fn tiles(count: usize) -> PartialTileSet {
+    // every tile filled with color 1 in plane 0
+    let data = (0..count * 32)
+        .map(|i| {
+            if i % 16 % 2 == 0 && i % 32 < 16 {
+                0xff
+            } else {
+                0
+            }
+        })
+        .collect::<Vec<_>>();
+    PartialTileSet::try_from_slice(&data).unwrap()
+}
+
+#[test]
+fn test_sheet_layout() {
+    let sheet = TileSheet::default();
+    assert_eq!(sheet.dimensions(40), (16 * 32, 3 * 32));
+    assert_eq!(sheet.dimensions(3), (3 * 32, 32));
+    assert_eq!(sheet.tile_position(17), (32, 32));
+
+    let grid = TileSheet {
+        grid: true,
+        ..Default::default()
+    };
+    assert_eq!(grid.dimensions(40), (16 * 33 + 1, 3 * 33 + 1));
+    assert_eq!(grid.tile_position(17), (34, 34));
+}
+
+#[test]
+fn test_sheet_render() {
+    let tiles = tiles(20);
+    let sheet = TileSheet {
+        grid: true,
+        labels: true,
+        ..Default::default()
+    };
+
+    let image = sheet.render(tiles.tiles(), &BW_PALETTE);
+    assert_eq!(image.dimensions(), sheet.dimensions(20));
+
+    let white = image::Rgba([255, 255, 255, 255]);
+    let (x, y) = sheet.tile_position(19);
+    assert_ne!(*image.get_pixel(x - 1, y + 20), white);
+    assert_eq!(*image.get_pixel(x + 20, y + 20), white);
+
+    // the label sits on a black box in the corner
+    assert_eq!(*image.get_pixel(x, y), image::Rgba([0, 0, 0, 255]));
+
+    // cells without a tile stay empty
+    let (x, y) = sheet.tile_position(25);
+    assert_eq!(image.get_pixel(x + 20, y + 20).0[3], 0);
+}
@@ -0,0 +1,72 @@
+use thanatos::{assemble, CompressionStats, Decompressor, OperationKind};
+
+#[test]
+fn test_stats() -> anyhow::Result<()> {
+    let src = r#"
+        copy 4 "00 11 22 33"
+        backread count=2 back=4
+        copy 1 "44"
+        backref count=4 back=6 ; re-reads the backread and the copy
+        exit
+    "#;
+    let compressed = assemble(src)?;
+
+    let (trace, result) = Decompressor::new(&compressed, 0).decompress_traced();
+    let stats = CompressionStats::from_trace(&trace, &result?);
+
+    assert_eq!(stats.compressed_size, compressed.len());
+    assert_eq!(stats.decompressed_size, 10);
+    assert_eq!(stats.count(OperationKind::CopySimple), 3);
+    assert_eq!(stats.count(OperationKind::CopyBackread), 2);
+    assert_eq!(stats.count(OperationKind::StartBackref), 1);
+    assert_eq!(stats.count(OperationKind::RepeatValue), 0);
+    assert_eq!(stats.count(OperationKind::Exit), 1);
+    assert_eq!(stats.longest_backread, 4);
+    assert_eq!(stats.longest_backref, 6);
+    assert_eq!(stats.nested_backreads, 1);
+    assert_eq!(stats.literal_bytes, 4 + 1 + 1);
+
+    Ok(())
+}
+
+#[test]
+#[cfg(feature = "std")]
+fn test_region_stats() -> anyhow::Result<()> {
+    use thanatos::{RegionKind, Rom, RomMap};
+
+    let mut data = assemble("repeat 0x200 0x00\nexit")?;
+    let tiles_offset = data.len();
+    data.extend(assemble(
+        "copy 4 \"01 02 03 04\"\nbackread count=0x1c back=4\nexit",
+    )?);
+
+    let map = RomMap::parse(&format!(
+        r#"
+        supported_roms = []
+        sprite = []
+
+        [[palette]]
+        name = "base"
+        layout = [{{ region = 0 }}]
+
+        [[tileset]]
+        name = "tiles"
+        layout = [{{ region = {}, offset = 0 }}]
+        "#,
+        tiles_offset
+    ))?;
+
+    let regions = map.stats(&Rom::new(&data))?;
+    assert_eq!(regions.len(), 2);
+
+    assert_eq!(regions[0].region.kind, RegionKind::Palette);
+    assert_eq!(regions[0].stats.decompressed_size, 0x200);
+    assert_eq!(regions[0].stats.compressed_size, tiles_offset);
+
+    assert_eq!(regions[1].region.name, "tiles");
+    assert_eq!(regions[1].stats.decompressed_size, 0x20);
+    assert_eq!(regions[1].stats.longest_backread, 4);
+    assert_eq!(regions[1].ratio, 9.0 / 32.0);
+
+    Ok(())
+}
@@ -0,0 +1,69 @@
+use std::{collections::HashSet, fs, mem};
+use thanatos::{Compressor, Decompressor, Operation, TraceEvent};
+
+#[test]
+fn test_trace() -> anyhow::Result<()> {
+    let mut seen = HashSet::new();
+
+    for entry in fs::read_dir("tests/decompress_data")? {
+        let data = fs::read(entry?.path())?;
+        let compressed = Compressor::new(&data).compress();
+
+        let (trace, result) = Decompressor::new(&compressed, 0).decompress_traced();
+        assert_eq!(result?.data, data);
+
+        let mut output_end = 0;
+        let mut in_backref = false;
+        for event in trace {
+            match event {
+                TraceEvent::Operation(operation) => {
+                    assert_eq!(operation.output.start, output_end);
+                    assert_eq!(operation.raw.len(), operation.operation.encoded_len());
+                    assert_eq!(operation.payload.len(), operation.operation.payload_len());
+                    assert_eq!(operation.output.len(), operation.operation.output_len());
+                    output_end = operation.output.end;
+
+                    seen.insert(mem::discriminant(&operation.operation));
+                }
+                TraceEvent::BackrefStart { from, to, .. } => {
+                    assert!(to < from);
+                    in_backref = true;
+                }
+                TraceEvent::BackrefReturn { .. } => {
+                    assert!(in_backref, "returned from a backref that was never started");
+                    in_backref = false;
+                }
+            }
+        }
+        assert_eq!(output_end, data.len());
+    }
+
+    let all = [
+        Operation::CopySimple(0),
+        Operation::CopyNibbleFixed {
+            count: 0,
+            fixed: 0,
+            fixed_pos: thanatos::NibblePos::Upper,
+            initial: None,
+        },
+        Operation::CopyDoubled(0),
+        Operation::CopyInterleaved {
+            count: 0,
+            fixed_value: 0,
+            fixed_first: false,
+        },
+        Operation::CopyBackread { count: 0, back: 0 },
+        Operation::RepeatValue { count: 0, value: 0 },
+        Operation::StartBackref { count: 0, back: 0 },
+        Operation::Exit,
+    ];
+    for operation in all {
+        assert!(
+            seen.contains(&mem::discriminant(&operation)),
+            "compressor never emitted {:?}",
+            operation
+        );
+    }
+
+    Ok(())
+}